@@ -1 +1,23 @@
+pub(crate) mod anonymize;
+pub(crate) mod changes;
+pub(crate) mod classify;
+pub(crate) mod columns;
+pub(crate) mod config;
+pub(crate) mod diagnostics;
+pub(crate) mod documents;
+pub(crate) mod fingerprint;
+pub(crate) mod fix;
+pub(crate) mod format;
+pub(crate) mod lint;
+pub(crate) mod minify;
+pub(crate) mod parameters;
+pub(crate) mod position;
+pub(crate) mod query;
+pub(crate) mod resources;
+pub(crate) mod statements;
+pub(crate) mod syntax;
+pub(crate) mod tables;
+pub(crate) mod tree_output;
 pub(crate) mod tree_sitter_sql;
+pub(crate) mod watch;
+pub(crate) mod workspace;
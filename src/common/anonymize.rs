@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::minify::leaf_tokens;
+use super::syntax::{is_literal, node_text, normalize_identifier};
+use super::tree_sitter_sql::parse;
+
+/// 置き換えた値の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    String,
+    Number,
+    /// 日付・日時の形をした文字列
+    Date,
+    Identifier,
+}
+
+/// 元の値と仮名の対応 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct Pseudonym {
+    pub kind: ValueKind,
+    /// 元の SQL に書かれていたとおりのテキスト
+    pub original: String,
+    pub pseudonym: String,
+}
+
+/// 匿名化の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct Anonymized {
+    pub sql: String,
+    /// 仮名から元の値に戻すための対応 (初出順)
+    pub mapping: Vec<Pseudonym>,
+    /// 匿名化した SQL が構文エラーなくパースできるか
+    pub valid: bool,
+}
+
+/// リテラル (と、指定があれば識別子) を仮名に置き換える
+/// 同じ値は呼び出しの中で常に同じ仮名になる。空白とコメントはそのまま残す
+pub fn anonymize(tree: &Tree, src: &str, identifiers: bool) -> Anonymized {
+    let mut pseudonyms = Pseudonyms::default();
+    let mut sql = String::new();
+    let mut offset = 0;
+
+    for leaf in leaf_tokens(tree.root_node(), src) {
+        let text = node_text(leaf, src);
+        let replacement = if is_literal(leaf) {
+            pseudonyms.literal(leaf, text)
+        } else if identifiers && is_anonymizable_identifier(leaf) {
+            Some(pseudonyms.get(ValueKind::Identifier, text))
+        } else {
+            None
+        };
+
+        if let Some(replacement) = replacement {
+            sql.push_str(&src[offset..leaf.start_byte()]);
+            sql.push_str(&replacement);
+            offset = leaf.end_byte();
+        }
+    }
+    sql.push_str(&src[offset..]);
+
+    Anonymized {
+        valid: !parse(&sql).root_node().has_error(),
+        sql,
+        mapping: pseudonyms.mapping,
+    }
+}
+
+#[derive(Default)]
+struct Pseudonyms {
+    /// (種類, 比較用の値) から mapping の添字
+    seen: HashMap<(ValueKind, String), usize>,
+    counts: HashMap<ValueKind, usize>,
+    mapping: Vec<Pseudonym>,
+}
+
+impl Pseudonyms {
+    /// TRUE や NULL など、数値でも文字列でもないリテラルは置き換えない
+    fn literal(&mut self, node: Node, text: &str) -> Option<String> {
+        if node.kind() == "number" || text.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            Some(self.get(ValueKind::Number, text))
+        } else if text
+            .strip_prefix('\'')
+            .is_some_and(|content| is_date(content.trim_end_matches('\'')))
+        {
+            Some(self.get(ValueKind::Date, text))
+        } else if text.contains('\'') || text.starts_with('$') {
+            Some(self.get(ValueKind::String, text))
+        } else {
+            None
+        }
+    }
+
+    fn get(&mut self, kind: ValueKind, text: &str) -> String {
+        let key = match kind {
+            ValueKind::Identifier => normalize_identifier(text),
+            _ => text.to_string(),
+        };
+        if let Some(&index) = self.seen.get(&(kind, key.clone())) {
+            return self.mapping[index].pseudonym.clone();
+        }
+
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        let pseudonym = match kind {
+            ValueKind::String => format!("'string_{}'", count),
+            ValueKind::Number if text.contains(['.', 'e', 'E']) => format!("{}.0", count),
+            ValueKind::Number => count.to_string(),
+            ValueKind::Date => fake_date(*count, text),
+            ValueKind::Identifier => format!("identifier_{}", count),
+        };
+
+        self.seen.insert((kind, key), self.mapping.len());
+        self.mapping.push(Pseudonym {
+            kind,
+            original: text.to_string(),
+            pseudonym: pseudonym.clone(),
+        });
+        pseudonym
+    }
+}
+
+/// 関数名と型名を除いた識別子か
+fn is_anonymizable_identifier(node: Node) -> bool {
+    if node.kind() != "identifier" {
+        return false;
+    }
+    let Some(parent) = node.parent() else {
+        return true;
+    };
+    if parent.kind().contains("type") {
+        return false;
+    }
+    // 関数呼び出しの関数名 (`count(*)` の count)
+    !(parent.kind() == "function_call"
+        && (parent.child_by_field_name("function") == Some(node)
+            || parent.named_child(0) == Some(node)))
+}
+
+/// `YYYY-MM-DD` で始まる文字列か
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+        && bytes[10..]
+            .iter()
+            .all(|b| b.is_ascii_digit() || b" :.+-T".contains(b))
+}
+
+/// n 番目の仮の日付。元の値に時刻があれば 0 時を付ける
+fn fake_date(n: usize, original: &str) -> String {
+    let n = n - 1;
+    let date = format!(
+        "{:04}-{:02}-{:02}",
+        2000 + n / (28 * 12),
+        n / 28 % 12 + 1,
+        n % 28 + 1
+    );
+    let content = original.trim_matches('\'');
+    if content.len() > 10 {
+        format!("'{} 00:00:00'", date)
+    } else {
+        format!("'{}'", date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anonymized(src: &str, identifiers: bool) -> Anonymized {
+        anonymize(&parse(src), src, identifiers)
+    }
+
+    #[test]
+    fn same_value_same_pseudonym() {
+        let result = anonymized(
+            "SELECT a FROM t WHERE b = 'x' OR c = 'x' OR d = 'y' OR e = 5 OR f = 5;",
+            false,
+        );
+        assert_eq!(
+            result.sql,
+            "SELECT a FROM t WHERE b = 'string_1' OR c = 'string_1' OR d = 'string_2' OR e = 1 OR f = 1;"
+        );
+        assert_eq!(
+            result
+                .mapping
+                .iter()
+                .map(|pseudonym| (pseudonym.kind, pseudonym.original.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (ValueKind::String, "'x'"),
+                (ValueKind::String, "'y'"),
+                (ValueKind::Number, "5")
+            ]
+        );
+        assert!(result.valid);
+    }
+
+    #[test]
+    fn dates_keep_their_shape() {
+        let result = anonymized(
+            "SELECT a FROM t WHERE d >= '2024-05-01' AND ts < '2024-05-01 12:30:00';",
+            false,
+        );
+        assert_eq!(
+            result.sql,
+            "SELECT a FROM t WHERE d >= '2000-01-01' AND ts < '2000-01-02 00:00:00';"
+        );
+    }
+
+    #[test]
+    fn identifiers_only_when_requested() {
+        let src = "SELECT count(*), name FROM users WHERE id = 1;";
+        assert_eq!(
+            anonymized(src, false).sql,
+            "SELECT count(*), name FROM users WHERE id = 1;"
+        );
+        assert_eq!(
+            anonymized(src, true).sql,
+            "SELECT count(*), identifier_1 FROM identifier_2 WHERE identifier_3 = 1;"
+        );
+    }
+
+    #[test]
+    fn invalid_output_is_reported() {
+        assert!(!anonymized("SELECT 'x' FROM WHERE;", false).valid);
+    }
+}
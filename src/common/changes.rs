@@ -0,0 +1,306 @@
+use std::collections::BTreeSet;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{InputEdit, Node};
+
+use super::position::{PositionEncoding, SourceRange, point_at};
+use super::statements::{statement_kind, statement_spans};
+use super::tree_sitter_sql::{parse, reparse};
+
+/// 古い SQL を新しい SQL にした編集 (tree-sitter の InputEdit のバイト位置)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Edit {
+    pub start_byte: usize,
+    /// 古い SQL での、置き換えた範囲の終了位置
+    pub old_end_byte: usize,
+    /// 新しい SQL での、置き換え後の範囲の終了位置
+    pub new_end_byte: usize,
+}
+
+impl Edit {
+    /// 共通の先頭と末尾を除いた範囲を、古い SQL から新しい SQL への編集とする
+    pub fn between(old: &str, new: &str) -> Self {
+        let mut prefix = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let mut suffix = old
+            .bytes()
+            .rev()
+            .zip(new.bytes().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix)
+        {
+            suffix -= 1;
+        }
+
+        Self {
+            start_byte: prefix,
+            old_end_byte: old.len() - suffix,
+            new_end_byte: new.len() - suffix,
+        }
+    }
+
+    /// old に対するこの編集を tree-sitter の InputEdit にする
+    pub fn input_edit(&self, old: &str, new: &str) -> InputEdit {
+        InputEdit {
+            start_byte: self.start_byte,
+            old_end_byte: self.old_end_byte,
+            new_end_byte: self.new_end_byte,
+            start_position: point_at(old, self.start_byte),
+            old_end_position: point_at(old, self.old_end_byte),
+            new_end_position: point_at(new, self.new_end_byte),
+        }
+    }
+
+    /// old と new に対して範囲が正しいか
+    pub fn validate(&self, old: &str, new: &str) -> Result<(), String> {
+        let valid = self.start_byte <= self.old_end_byte
+            && self.start_byte <= self.new_end_byte
+            && old.is_char_boundary(self.old_end_byte)
+            && new.is_char_boundary(self.new_end_byte)
+            && old.len() - self.old_end_byte == new.len() - self.new_end_byte
+            && old
+                .get(..self.start_byte)
+                .is_some_and(|prefix| new.get(..self.start_byte) == Some(prefix))
+            && old[self.old_end_byte..] == new[self.new_end_byte..];
+        if valid {
+            Ok(())
+        } else {
+            Err(format!(
+                "edit {}..{} -> {}..{} does not turn old_sql ({} bytes) into new_sql ({} bytes)",
+                self.start_byte,
+                self.old_end_byte,
+                self.start_byte,
+                self.new_end_byte,
+                old.len(),
+                new.len()
+            ))
+        }
+    }
+}
+
+/// 構文が変わった範囲 1 つ
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedRange {
+    /// 新しい SQL での範囲
+    #[serde(flatten)]
+    pub range: SourceRange,
+    /// 範囲を覆う最小の名前付きノードの種類
+    pub kind: Option<&'static str>,
+    /// 範囲の中で始まる名前付きノードの種類
+    pub kinds: BTreeSet<&'static str>,
+}
+
+/// 変更の影響を受けた文
+#[derive(Debug, Clone, Serialize)]
+pub struct AffectedStatement {
+    /// 新しい SQL での文の番号
+    pub index: usize,
+    pub kind: Option<&'static str>,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// 2 つの版の間の変更
+#[derive(Debug, Clone, Serialize)]
+pub struct Changes {
+    pub edit: Edit,
+    /// Tree::changed_ranges が返した、構文が変わった範囲
+    pub changed_ranges: Vec<ChangedRange>,
+    /// 編集された範囲か構文が変わった範囲と重なる文
+    pub statements: Vec<AffectedStatement>,
+}
+
+/// 古い SQL のツリーに編集を反映して新しい SQL をパースし直し、変わった範囲を求める
+/// 範囲の列は encoding の単位で数える
+pub fn changed_ranges(old: &str, new: &str, edit: Edit, encoding: PositionEncoding) -> Changes {
+    let mut old_tree = parse(old);
+    old_tree.edit(&edit.input_edit(old, new));
+    let new_tree = reparse(new, &old_tree);
+    let root = new_tree.root_node();
+
+    let changed_ranges: Vec<_> = old_tree
+        .changed_ranges(&new_tree)
+        .map(|range| {
+            let mut kinds = BTreeSet::new();
+            collect_kinds(root, range.start_byte, range.end_byte, &mut kinds);
+
+            ChangedRange {
+                range: SourceRange::from_bytes(new, range.start_byte, range.end_byte, encoding),
+                kind: root
+                    .named_descendant_for_byte_range(range.start_byte, range.end_byte)
+                    .map(|node| node.kind()),
+                kinds,
+            }
+        })
+        .collect();
+
+    // 字句の中だけの変更 (リテラルの値など) は changed_ranges に現れないため、編集の範囲も含める
+    let mut ranges = vec![(edit.start_byte, edit.new_end_byte)];
+    ranges.extend(
+        changed_ranges
+            .iter()
+            .map(|changed| (changed.range.start_byte, changed.range.end_byte)),
+    );
+    let statements = statement_spans(new)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, span)| {
+            let end = span
+                .terminator
+                .map_or(span.end, |terminator| terminator + 1);
+            ranges
+                .iter()
+                .any(|&(start_byte, end_byte)| span.start <= end_byte && start_byte <= end)
+        })
+        .map(|(index, span)| AffectedStatement {
+            index,
+            kind: statement_kind(&span.parse(new)),
+            range: SourceRange::from_bytes(new, span.start, span.end, encoding),
+        })
+        .collect();
+
+    Changes {
+        edit,
+        changed_ranges,
+        statements,
+    }
+}
+
+fn collect_kinds(
+    node: Node,
+    start_byte: usize,
+    end_byte: usize,
+    kinds: &mut BTreeSet<&'static str>,
+) {
+    if node.end_byte() < start_byte || node.start_byte() > end_byte {
+        return;
+    }
+    if node.is_named() && node.start_byte() >= start_byte && node.start_byte() < end_byte {
+        kinds.insert(node.kind());
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_kinds(child, start_byte, end_byte, kinds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start_byte: usize, old_end_byte: usize, new_end_byte: usize) -> Edit {
+        Edit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+        }
+    }
+
+    fn affected(old: &str, new: &str) -> Vec<usize> {
+        changed_ranges(old, new, Edit::between(old, new), PositionEncoding::Utf8)
+            .statements
+            .iter()
+            .map(|statement| statement.index)
+            .collect()
+    }
+
+    #[test]
+    fn between_trims_common_prefix_and_suffix() {
+        assert_eq!(
+            Edit::between("SELECT a FROM t;", "SELECT a, b FROM t;"),
+            edit(8, 8, 11)
+        );
+        assert_eq!(Edit::between("SELECT 1;", "-- c\nSELECT 1;"), edit(0, 0, 5));
+        assert_eq!(
+            Edit::between("SELECT 1;", "SELECT 1;\nSELECT 2;"),
+            edit(9, 9, 19)
+        );
+        assert_eq!(
+            Edit::between("SELECT 1;\nSELECT 2;", "SELECT 2;"),
+            edit(7, 17, 7)
+        );
+        assert_eq!(Edit::between("SELECT 1;", "SELECT 1;"), edit(9, 9, 9));
+    }
+
+    #[test]
+    fn between_keeps_multibyte_characters_whole() {
+        // 'é' (C3 A9) と 'è' (C3 A8) は先頭のバイトが同じ
+        assert_eq!(Edit::between("SELECT 'é';", "SELECT 'è';"), edit(8, 10, 10));
+        // 'à' (C3 A0) と 'Ġ' (C4 A0) は末尾のバイトが同じ
+        assert_eq!(Edit::between("'à'", "'Ġ'"), edit(1, 3, 3));
+        assert_eq!(Edit::between("'é'", "'éé'"), edit(3, 3, 5));
+
+        for (old, new) in [
+            ("SELECT 'é';", "SELECT 'è';"),
+            ("'à'", "'Ġ'"),
+            ("'é'", "'éé'"),
+            ("😀", "😁"),
+        ] {
+            let edit = Edit::between(old, new);
+            assert!(edit.validate(old, new).is_ok(), "{:?}", edit);
+            assert!(old.is_char_boundary(edit.start_byte));
+        }
+    }
+
+    #[test]
+    fn validate_rejects_edits_that_do_not_match() {
+        let (old, new) = ("SELECT 'é';", "SELECT 'è';");
+        assert!(edit(8, 10, 10).validate(old, new).is_ok());
+        // 文字の途中
+        assert!(edit(9, 10, 10).validate(old, new).is_err());
+        assert!(edit(8, 9, 9).validate(old, new).is_err());
+        // 前後の変わっていない部分が一致しない
+        assert!(edit(0, 5, 5).validate(old, new).is_err());
+        assert!(edit(8, 10, 11).validate(old, new).is_err());
+        // 範囲が逆、またはテキストの外
+        assert!(edit(10, 8, 8).validate(old, new).is_err());
+        assert!(edit(8, 20, 20).validate(old, new).is_err());
+        assert!(edit(20, 20, 20).validate(old, new).is_err());
+    }
+
+    #[test]
+    fn affected_statements() {
+        let old = "SELECT 1;\nSELECT 2;\nSELECT 3;";
+        assert_eq!(affected(old, "SELECT 1;\nSELECT 5;\nSELECT 3;"), vec![1]);
+        assert_eq!(affected(old, "SELECT 0;\nSELECT 2;\nSELECT 3;"), vec![0]);
+        assert_eq!(affected(old, "SELECT 1;\nSELECT 2;\nSELECT 4;"), vec![2]);
+        // 文の前に挿入したコメントは最初の文に含まれる
+        assert_eq!(affected(old, &format!("-- c\n{}", old)), vec![0]);
+        // 末尾の文を消すと、その直前で終わる文が影響を受けた文になる
+        assert_eq!(affected(old, "SELECT 1;\nSELECT 2;"), vec![1]);
+        // 文をまたぐ編集
+        assert_eq!(affected(old, "SELECT 1, 3;"), vec![0]);
+    }
+
+    #[test]
+    fn ranges_count_columns_in_encoding() {
+        let old = "SELECT 'é', 1;";
+        let new = "SELECT 'é', 2;";
+        let changes = |encoding| changed_ranges(old, new, Edit::between(old, new), encoding);
+
+        let utf8 = changes(PositionEncoding::Utf8);
+        let utf16 = changes(PositionEncoding::Utf16);
+        assert_eq!(utf8.edit, edit(13, 14, 14));
+        assert_eq!(utf8.statements.len(), 1);
+        assert_eq!(
+            utf8.statements[0].range.end_byte,
+            utf16.statements[0].range.end_byte
+        );
+        assert_eq!(
+            utf8.statements[0].range.end.column,
+            utf16.statements[0].range.end.column + 1
+        );
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::{statement_kind, statement_kind_of, statement_spans};
+use super::syntax::{
+    has_keyword, is_keyword, is_name, node_text, normalize_identifier, split_qualified_name,
+};
+
+/// 呼び出すと書き込みやセッションへの副作用がある組み込み関数
+const SIDE_EFFECT_FUNCTIONS: &[&str] = &[
+    "nextval",
+    "setval",
+    "pg_terminate_backend",
+    "pg_cancel_backend",
+    "pg_advisory_lock",
+    "pg_advisory_xact_lock",
+    "pg_try_advisory_lock",
+    "pg_try_advisory_xact_lock",
+    "pg_notify",
+    "set_config",
+    "pg_reload_conf",
+    "pg_rotate_logfile",
+    "pg_switch_wal",
+    "lo_import",
+    "lo_export",
+    "lo_unlink",
+    "dblink_exec",
+];
+
+/// 副作用のない組み込み関数
+/// これ以外の関数 (ユーザー定義関数を含む) は中身を判定できないため、読み取り専用とみなさない
+const READ_ONLY_FUNCTIONS: &[&str] = &[
+    // 集約関数とウィンドウ関数
+    "count",
+    "sum",
+    "avg",
+    "min",
+    "max",
+    "array_agg",
+    "string_agg",
+    "json_agg",
+    "jsonb_agg",
+    "json_object_agg",
+    "jsonb_object_agg",
+    "bool_and",
+    "bool_or",
+    "every",
+    "bit_and",
+    "bit_or",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "variance",
+    "var_pop",
+    "var_samp",
+    "mode",
+    "percentile_cont",
+    "percentile_disc",
+    "row_number",
+    "rank",
+    "dense_rank",
+    "percent_rank",
+    "cume_dist",
+    "ntile",
+    "lag",
+    "lead",
+    "first_value",
+    "last_value",
+    "nth_value",
+    // 条件
+    "coalesce",
+    "nullif",
+    "greatest",
+    "least",
+    // 文字列
+    "lower",
+    "upper",
+    "length",
+    "char_length",
+    "character_length",
+    "octet_length",
+    "substring",
+    "substr",
+    "trim",
+    "ltrim",
+    "rtrim",
+    "btrim",
+    "replace",
+    "concat",
+    "concat_ws",
+    "left",
+    "right",
+    "lpad",
+    "rpad",
+    "position",
+    "strpos",
+    "split_part",
+    "reverse",
+    "repeat",
+    "initcap",
+    "format",
+    "translate",
+    "starts_with",
+    "regexp_replace",
+    "regexp_match",
+    "regexp_matches",
+    "regexp_split_to_array",
+    "md5",
+    "to_hex",
+    "quote_ident",
+    "quote_literal",
+    // 数値
+    "abs",
+    "ceil",
+    "ceiling",
+    "floor",
+    "round",
+    "trunc",
+    "mod",
+    "power",
+    "sqrt",
+    "exp",
+    "ln",
+    "log",
+    "sign",
+    "random",
+    "width_bucket",
+    // 日時
+    "now",
+    "current_timestamp",
+    "current_date",
+    "clock_timestamp",
+    "statement_timestamp",
+    "transaction_timestamp",
+    "date_trunc",
+    "date_part",
+    "extract",
+    "age",
+    "to_char",
+    "to_date",
+    "to_timestamp",
+    "to_number",
+    "make_date",
+    "make_timestamp",
+    "make_interval",
+    // JSON と配列
+    "to_json",
+    "to_jsonb",
+    "json_build_object",
+    "jsonb_build_object",
+    "json_build_array",
+    "jsonb_build_array",
+    "json_extract_path",
+    "jsonb_extract_path",
+    "json_extract_path_text",
+    "jsonb_extract_path_text",
+    "json_array_elements",
+    "jsonb_array_elements",
+    "json_each",
+    "jsonb_each",
+    "json_typeof",
+    "jsonb_typeof",
+    "json_array_length",
+    "jsonb_array_length",
+    "jsonb_set",
+    "jsonb_strip_nulls",
+    "array_length",
+    "array_to_string",
+    "string_to_array",
+    "array_append",
+    "array_prepend",
+    "array_cat",
+    "array_position",
+    "cardinality",
+    "unnest",
+    "generate_series",
+    // その他
+    "pg_typeof",
+    "version",
+    "current_setting",
+    "gen_random_uuid",
+];
+
+/// 文の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementCategory {
+    /// SELECT などの問い合わせ
+    Dql,
+    /// INSERT / UPDATE / DELETE / MERGE などのデータ操作
+    Dml,
+    /// CREATE / ALTER / DROP / TRUNCATE などの定義操作
+    Ddl,
+    /// GRANT / REVOKE
+    Dcl,
+    /// BEGIN / COMMIT / ROLLBACK などのトランザクション制御
+    Tcl,
+    /// SET などのセッション操作
+    Other,
+    /// 構文エラーなどで判別できない
+    Unknown,
+}
+
+impl StatementCategory {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Dql => "DQL",
+            Self::Dml => "DML",
+            Self::Ddl => "DDL",
+            Self::Dcl => "DCL",
+            Self::Tcl => "transaction control",
+            Self::Other => "session",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// 文 1 つの分類結果
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementClassification {
+    pub index: usize,
+    pub kind: Option<&'static str>,
+    pub category: StatementCategory,
+    pub read_only: bool,
+    /// 読み取り専用でないと判断した理由
+    pub reasons: Vec<String>,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// スクリプト全体の分類結果
+#[derive(Debug, Clone, Serialize)]
+pub struct Classification {
+    /// 文が 1 つ以上あり、すべての文が読み取り専用の場合のみ true
+    pub read_only: bool,
+    pub statements: Vec<StatementClassification>,
+}
+
+/// スクリプトの文をそれぞれ分類し、全体が読み取り専用かを判定する
+/// 判別できない文と、文のない (空またはコメントだけの) スクリプトは読み取り専用とみなさない
+pub fn classify(src: &str, encoding: PositionEncoding) -> Classification {
+    let statements: Vec<_> = statement_spans(src)
+        .into_iter()
+        .enumerate()
+        .map(|(index, span)| {
+            let tree = span.parse(src);
+            let kind = statement_kind(&tree);
+            let category = kind.map_or(StatementCategory::Unknown, category_of);
+            let reasons = write_reasons(&tree, src, category);
+
+            StatementClassification {
+                index,
+                kind,
+                category,
+                read_only: reasons.is_empty(),
+                reasons,
+                range: SourceRange::from_bytes(src, span.start, span.end, encoding),
+            }
+        })
+        .collect();
+
+    Classification {
+        read_only: !statements.is_empty() && statements.iter().all(|statement| statement.read_only),
+        statements,
+    }
+}
+
+/// 文の種類 (`_statement` を除いた名前) から分類を決める
+pub fn category_of(kind: &str) -> StatementCategory {
+    match kind {
+        "select" | "values" | "show" | "explain" | "table" => StatementCategory::Dql,
+        "insert" | "update" | "delete" | "merge" | "copy" | "call" => StatementCategory::Dml,
+        "grant" | "revoke" => StatementCategory::Dcl,
+        "begin" | "start_transaction" | "commit" | "end" | "rollback" | "savepoint"
+        | "release_savepoint" | "transaction" | "set_transaction" => StatementCategory::Tcl,
+        "drop" | "truncate" | "comment" | "rename" => StatementCategory::Ddl,
+        kind if kind.starts_with("create") || kind.starts_with("alter") => StatementCategory::Ddl,
+        _ => StatementCategory::Other,
+    }
+}
+
+/// 文が読み取り専用でない理由を集める
+/// DQL の文でも、CTE の中の DML や SELECT ... INTO、行ロック、副作用のない組み込み関数以外の呼び出しは書き込みとみなす
+fn write_reasons(tree: &Tree, src: &str, category: StatementCategory) -> Vec<String> {
+    let root = tree.root_node();
+    let mut reasons = Vec::new();
+
+    if root.has_error() {
+        reasons.push("statement has syntax errors".to_string());
+    }
+    match category {
+        StatementCategory::Dql => {}
+        StatementCategory::Unknown => reasons.push("statement kind is unknown".to_string()),
+        category => reasons.push(format!("{} statement", category.label())),
+    }
+
+    visit(root, src, &mut reasons);
+    let mut seen = HashSet::new();
+    reasons.retain(|reason| seen.insert(reason.clone()));
+
+    reasons
+}
+
+fn visit(node: Node, src: &str, reasons: &mut Vec<String>) {
+    // トップレベルの文自体は category で判定済み
+    let nested = node
+        .parent()
+        .is_some_and(|parent| parent.parent().is_some());
+
+    if nested
+        && let Some(kind) = statement_kind_of(node)
+        && category_of(kind) != StatementCategory::Dql
+    {
+        reasons.push(format!("nested {} statement", kind.to_uppercase()));
+    }
+
+    let kind = node.kind().to_ascii_lowercase();
+    if kind == "into_clause"
+        || (matches!(kind.as_str(), "select_statement" | "select_clause")
+            && has_keyword(node, "INTO"))
+    {
+        reasons.push("SELECT ... INTO creates a table".to_string());
+    }
+    if kind.contains("for_update") || kind.contains("locking") || is_locking_clause(node) {
+        reasons.push("locking clause (FOR UPDATE / FOR SHARE)".to_string());
+    }
+    if let Some(reason) = function_call_reason(node, src) {
+        reasons.push(reason);
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(child, src, reasons);
+    }
+}
+
+/// 関数呼び出しが読み取り専用と言えない場合、その理由
+fn function_call_reason(node: Node, src: &str) -> Option<String> {
+    if node.kind() != "function_call" {
+        return None;
+    }
+    let function = node
+        .child_by_field_name("function")
+        .or_else(|| node.named_child(0))
+        .filter(|function| is_name(*function))?;
+    let text = node_text(function, src);
+    let mut parts = split_qualified_name(text);
+    let name = normalize_identifier(&parts.pop()?);
+    // 組み込み関数として扱うのは、スキーマなしか pg_catalog の関数だけ
+    let built_in = parts
+        .last()
+        .is_none_or(|schema| normalize_identifier(schema) == "pg_catalog");
+
+    if built_in && SIDE_EFFECT_FUNCTIONS.contains(&name.as_str()) {
+        Some(format!("{}() has side effects", name))
+    } else if built_in && READ_ONLY_FUNCTIONS.contains(&name.as_str()) {
+        None
+    } else {
+        Some(format!("{}() may have side effects", text))
+    }
+}
+
+/// 子に `FOR UPDATE` / `FOR SHARE` などのキーワード列を持つか
+fn is_locking_clause(node: Node) -> bool {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    children.windows(2).any(|pair| {
+        is_keyword(pair[0], "FOR")
+            && ["UPDATE", "SHARE", "NO", "KEY"]
+                .iter()
+                .any(|keyword| is_keyword(pair[1], keyword))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_scripts() {
+        for src in [
+            "SELECT 1;",
+            "SELECT a FROM t WHERE b = 1;",
+            "WITH x AS (SELECT * FROM t) SELECT * FROM x;",
+            "SELECT 1; SELECT 2;",
+            "SELECT count(*) FROM t;",
+        ] {
+            assert!(classify(src, PositionEncoding::Utf8).read_only, "{}", src);
+        }
+    }
+
+    #[test]
+    fn writing_scripts() {
+        for src in [
+            "WITH x AS (DELETE FROM t RETURNING *) SELECT * FROM x;",
+            "SELECT * INTO t2 FROM t;",
+            "INSERT INTO t SELECT * FROM s;",
+            "EXPLAIN ANALYZE DELETE FROM t;",
+            "SELECT * FROM t FOR UPDATE;",
+            "COPY t FROM '/tmp/t.csv';",
+            "DO $$ BEGIN DELETE FROM t; END $$;",
+            "SELECT nextval('s');",
+            "SELECT setval('s', 1);",
+            "SELECT pg_terminate_backend(1);",
+        ] {
+            assert!(!classify(src, PositionEncoding::Utf8).read_only, "{}", src);
+        }
+    }
+
+    #[test]
+    fn one_writing_statement_makes_script_writing() {
+        let classification = classify(
+            "SELECT 1; UPDATE t SET a = 1; SELECT 2;",
+            PositionEncoding::Utf8,
+        );
+        assert!(!classification.read_only);
+        let read_only: Vec<_> = classification
+            .statements
+            .iter()
+            .map(|statement| statement.read_only)
+            .collect();
+        assert_eq!(read_only, vec![true, false, true]);
+        assert_eq!(
+            classification.statements[1].category,
+            StatementCategory::Dml
+        );
+    }
+
+    #[test]
+    fn read_only_built_in_functions() {
+        for src in [
+            "SELECT lower(name), coalesce(a, 0) FROM t;",
+            "SELECT pg_catalog.upper(name) FROM t;",
+            "SELECT date_trunc('day', now());",
+        ] {
+            assert!(classify(src, PositionEncoding::Utf8).read_only, "{}", src);
+        }
+    }
+
+    #[test]
+    fn unknown_functions_are_not_read_only() {
+        for src in [
+            "SELECT purge_all();",
+            "SELECT * FROM t WHERE archive(id);",
+            "SELECT app.lower(name) FROM t;",
+        ] {
+            let classification = classify(src, PositionEncoding::Utf8);
+            assert!(!classification.read_only, "{}", src);
+            assert!(
+                classification.statements[0]
+                    .reasons
+                    .iter()
+                    .any(|reason| reason.contains("may have side effects")),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn scripts_without_statements_are_not_read_only() {
+        for src in ["", "  \n", "-- only a comment\n", "/* nothing */ ;"] {
+            let classification = classify(src, PositionEncoding::Utf8);
+            assert!(classification.statements.is_empty(), "{}", src);
+            assert!(!classification.read_only, "{}", src);
+        }
+    }
+
+    #[test]
+    fn syntax_errors_are_not_read_only() {
+        for src in ["SELECT FROM WHERE;", "SELECT (1;", "SELECT 1; SELEC 2;"] {
+            assert!(!classify(src, PositionEncoding::Utf8).read_only, "{}", src);
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+use super::syntax::{
+    child_of_kind, is_keyword, is_name, named_child_after_keyword, node_text, normalize_identifier,
+    split_qualified_name,
+};
+use super::tables::cte_names;
+
+/// 列スコープを作る文の種類
+const QUERY_KINDS: &[&str] = &[
+    "select_statement",
+    "insert_statement",
+    "update_statement",
+    "delete_statement",
+];
+
+/// 列参照が現れる句
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnClause {
+    Select,
+    Where,
+    GroupBy,
+    Having,
+    OrderBy,
+    JoinOn,
+    Set,
+    Insert,
+    Other,
+}
+
+/// 列の修飾子から解決したテーブル
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedTable {
+    /// テーブル名 (サブクエリの場合はその別名)
+    pub name: String,
+    pub schema: Option<String>,
+    /// WITH 句で定義された CTE の場合は true
+    pub is_cte: bool,
+    /// FROM 句のサブクエリの場合は true
+    pub is_subquery: bool,
+}
+
+/// 列参照 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnReference {
+    pub name: String,
+    /// 書かれたとおりの修飾子 (例: `u`、`public.users`)
+    pub qualifier: Option<String>,
+    /// 修飾子、または FROM 句のテーブルが 1 つだけの場合はそのテーブルから解決したテーブル
+    pub table: Option<ResolvedTable>,
+    pub clause: ColumnClause,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// ツリーから列参照を集める
+pub fn extract_columns(tree: &Tree, src: &str, encoding: PositionEncoding) -> Vec<ColumnReference> {
+    let mut collector = Collector {
+        src,
+        encoding,
+        scopes: Vec::new(),
+        cte_scopes: Vec::new(),
+        columns: Vec::new(),
+    };
+    collector.visit(tree.root_node());
+
+    collector
+        .columns
+        .sort_by_key(|column| column.range.start_byte);
+    collector.columns
+}
+
+/// FROM 句などで導入された、列の修飾子として使える名前
+struct Source {
+    /// 正規化した別名、または別名がなければテーブル名
+    key: String,
+    /// 正規化した修飾付きのテーブル名 (`schema.table` でも参照できるように)
+    qualified_key: Option<String>,
+    table: ResolvedTable,
+}
+
+struct Collector<'a> {
+    src: &'a str,
+    encoding: PositionEncoding,
+    /// 入れ子になった問い合わせごとのテーブル
+    scopes: Vec<Vec<Source>>,
+    /// 入れ子になった WITH 句ごとの CTE 名
+    cte_scopes: Vec<Vec<String>>,
+    columns: Vec<ColumnReference>,
+}
+
+/// `INSERT INTO t (a, b) ...` の、テーブル名の直後の括弧に並ぶ列名
+/// VALUES や SELECT、RETURNING、ON CONFLICT の中の式は含めない
+fn insert_columns(node: Node) -> Vec<Node> {
+    if node.kind() != "insert_statement" {
+        return Vec::new();
+    }
+    let Some(target) = named_child_after_keyword(node, "INTO") else {
+        return Vec::new();
+    };
+
+    let mut columns = Vec::new();
+    let mut cursor = node.walk();
+    let mut children = node
+        .children(&mut cursor)
+        .skip_while(|child| *child != target)
+        .skip(1)
+        .filter(|child| !child.is_extra());
+    match children.next() {
+        Some(open) if !open.is_named() && open.kind() == "(" => {
+            columns.extend(
+                children
+                    .take_while(|child| child.is_named() || child.kind() != ")")
+                    .filter(|child| is_name(*child)),
+            );
+        }
+        // 列の一覧が 1 つのノードにまとめられている場合
+        Some(list) if list.is_named() && list.kind().contains("column") => {
+            let mut cursor = list.walk();
+            columns.extend(
+                list.named_children(&mut cursor)
+                    .filter(|child| is_name(*child)),
+            );
+        }
+        _ => {}
+    }
+    columns
+}
+
+impl Collector<'_> {
+    fn visit(&mut self, node: Node) {
+        if QUERY_KINDS.contains(&node.kind()) {
+            self.query(node);
+            return;
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.visit(child);
+        }
+    }
+
+    /// 問い合わせ 1 つを処理する
+    /// CTE と FROM 句のサブクエリを先に処理し、次にこの問い合わせのテーブルをスコープに積んで各句を走査する
+    fn query(&mut self, node: Node) {
+        let with_clause = child_of_kind(node, "with_clause");
+        if let Some(with_clause) = with_clause {
+            self.cte_scopes.push(cte_names(with_clause, self.src));
+            self.visit(with_clause);
+        }
+
+        let sources = self.sources(node);
+        self.scopes.push(sources);
+
+        let insert_columns = insert_columns(node);
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if insert_columns.contains(&child) {
+                self.push(child, ColumnClause::Insert);
+                continue;
+            }
+            match child.kind() {
+                "with_clause" => {}
+                "from_clause" => self.join_conditions(child),
+                "join_clause" => self.join_clause(child),
+                "select_clause" => self.expression(child, ColumnClause::Select),
+                "where_clause" => self.expression(child, ColumnClause::Where),
+                "group_by_clause" => self.expression(child, ColumnClause::GroupBy),
+                "having_clause" => self.expression(child, ColumnClause::Having),
+                "order_by_clause" => self.expression(child, ColumnClause::OrderBy),
+                "set_clause" => self.expression(child, ColumnClause::Set),
+                // INSERT INTO t (a, b) の対象テーブル名は列ではない
+                _ if node.kind() == "insert_statement"
+                    && Some(child) == named_child_after_keyword(node, "INTO") => {}
+                _ if node.kind() == "update_statement"
+                    && Some(child) == named_child_after_keyword(node, "UPDATE") => {}
+                _ => self.expression(child, ColumnClause::Other),
+            }
+        }
+
+        self.scopes.pop();
+        if with_clause.is_some() {
+            self.cte_scopes.pop();
+        }
+    }
+
+    /// 問い合わせが参照するテーブルを集める
+    /// FROM 句のサブクエリはここで処理する
+    fn sources(&mut self, node: Node) -> Vec<Source> {
+        let mut sources = Vec::new();
+
+        let target = match node.kind() {
+            "insert_statement" => named_child_after_keyword(node, "INTO"),
+            "update_statement" => named_child_after_keyword(node, "UPDATE"),
+            _ => None,
+        };
+        if let Some(target) = target {
+            self.table_expression(target, &mut sources);
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if child.kind() == "from_clause" || child.kind() == "join_clause" {
+                self.sources_in(child, &mut sources);
+            }
+        }
+
+        sources
+    }
+
+    fn sources_in(&mut self, node: Node, sources: &mut Vec<Source>) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "join_clause" => self.sources_in(child, sources),
+                _ if node.kind() == "join_clause"
+                    && Some(child) != named_child_after_keyword(node, "JOIN") => {}
+                _ => self.table_expression(child, sources),
+            }
+        }
+    }
+
+    fn table_expression(&mut self, node: Node, sources: &mut Vec<Source>) {
+        if is_name(node) {
+            sources.push(self.table_source(node, None));
+            return;
+        }
+
+        if node.kind() != "alias" {
+            return;
+        }
+
+        let (Some(target), Some(alias)) = (
+            node.child_by_field_name("value")
+                .or_else(|| node.named_child(0)),
+            node.child_by_field_name("alias")
+                .or_else(|| node.named_child(node.named_child_count().saturating_sub(1))),
+        ) else {
+            return;
+        };
+        if target.id() == alias.id() {
+            return;
+        }
+
+        if is_name(target) {
+            sources.push(self.table_source(target, Some(alias)));
+        } else {
+            // (SELECT ...) AS sub
+            self.visit(target);
+            let name = node_text(alias, self.src).to_string();
+            sources.push(Source {
+                key: normalize_identifier(&name),
+                qualified_key: None,
+                table: ResolvedTable {
+                    name,
+                    schema: None,
+                    is_cte: false,
+                    is_subquery: true,
+                },
+            });
+        }
+    }
+
+    fn table_source(&self, name: Node, alias: Option<Node>) -> Source {
+        let mut parts = split_qualified_name(node_text(name, self.src));
+        let table = parts.pop().unwrap_or_default();
+        let schema = parts.pop();
+
+        let is_cte = schema.is_none() && {
+            let normalized = normalize_identifier(&table);
+            self.cte_scopes
+                .iter()
+                .any(|scope| scope.contains(&normalized))
+        };
+        let key = match alias {
+            Some(alias) => normalize_identifier(node_text(alias, self.src)),
+            None => normalize_identifier(&table),
+        };
+        let qualified_key = schema.as_ref().map(|schema| {
+            format!(
+                "{}.{}",
+                normalize_identifier(schema),
+                normalize_identifier(&table)
+            )
+        });
+
+        Source {
+            key,
+            qualified_key,
+            table: ResolvedTable {
+                name: table,
+                schema,
+                is_cte,
+                is_subquery: false,
+            },
+        }
+    }
+
+    fn join_conditions(&mut self, node: Node) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if child.kind() == "join_clause" {
+                self.join_clause(child);
+            }
+        }
+    }
+
+    /// JOIN ... ON の条件式
+    fn join_clause(&mut self, node: Node) {
+        let mut cursor = node.walk();
+        let mut after_on = false;
+        for child in node.children(&mut cursor) {
+            if is_keyword(child, "ON") {
+                after_on = true;
+            } else if child.kind() == "join_clause" {
+                self.join_clause(child);
+            } else if after_on && child.is_named() {
+                self.expression(child, ColumnClause::JoinOn);
+            }
+        }
+    }
+
+    /// 式を走査して列参照を集める
+    fn expression(&mut self, node: Node, clause: ColumnClause) {
+        if QUERY_KINDS.contains(&node.kind()) {
+            self.query(node);
+            return;
+        }
+
+        if is_name(node) {
+            self.push(node, clause);
+            return;
+        }
+
+        match node.kind() {
+            // 別名の定義は列ではない
+            "alias" => {
+                if let Some(value) = node
+                    .child_by_field_name("value")
+                    .or_else(|| node.named_child(0))
+                {
+                    self.expression(value, clause);
+                }
+            }
+            // 関数名は列ではない
+            "function_call" => {
+                let function = node
+                    .child_by_field_name("function")
+                    .or_else(|| node.named_child(0).filter(|child| is_name(*child)));
+                let mut cursor = node.walk();
+                for child in node.named_children(&mut cursor) {
+                    if Some(child) != function {
+                        self.expression(child, clause);
+                    }
+                }
+            }
+            // 型名は列ではない
+            kind if kind.ends_with("type") => {}
+            _ => {
+                let mut cursor = node.walk();
+                for child in node.named_children(&mut cursor) {
+                    self.expression(child, clause);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, node: Node, clause: ColumnClause) {
+        let mut parts = split_qualified_name(node_text(node, self.src));
+        let name = parts.pop().unwrap_or_default();
+        let qualifier = if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("."))
+        };
+
+        let table = self.resolve(qualifier.as_deref());
+
+        self.columns.push(ColumnReference {
+            name,
+            qualifier,
+            table,
+            clause,
+            range: SourceRange::new(node, self.src, self.encoding),
+        });
+    }
+
+    /// 修飾子を内側のスコープから順に探してテーブルに解決する
+    /// 修飾子がない場合は、最も内側のスコープのテーブルが 1 つだけのときに限り解決する
+    fn resolve(&self, qualifier: Option<&str>) -> Option<ResolvedTable> {
+        let Some(qualifier) = qualifier else {
+            return match self.scopes.last().map(Vec::as_slice) {
+                Some([source]) => Some(source.table.clone()),
+                _ => None,
+            };
+        };
+
+        let key = split_qualified_name(qualifier)
+            .iter()
+            .map(|part| normalize_identifier(part))
+            .collect::<Vec<_>>()
+            .join(".");
+
+        self.scopes.iter().rev().find_map(|scope| {
+            scope
+                .iter()
+                .find(|source| source.key == key || source.qualified_key.as_ref() == Some(&key))
+                .map(|source| source.table.clone())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tree_sitter_sql::parse;
+
+    /// (列名, 解決したテーブル, 句)
+    fn columns(src: &str) -> Vec<(String, Option<String>, ColumnClause)> {
+        extract_columns(&parse(src), src, PositionEncoding::Utf8)
+            .into_iter()
+            .map(|column| {
+                (
+                    column.name,
+                    column.table.map(|table| table.name),
+                    column.clause,
+                )
+            })
+            .collect()
+    }
+
+    fn column(
+        name: &str,
+        table: &str,
+        clause: ColumnClause,
+    ) -> (String, Option<String>, ColumnClause) {
+        (name.to_string(), Some(table.to_string()), clause)
+    }
+
+    #[test]
+    fn qualified_columns_through_aliases() {
+        assert_eq!(
+            columns("SELECT u.id, o.total FROM users u JOIN orders o ON u.id = o.user_id;"),
+            vec![
+                column("id", "users", ColumnClause::Select),
+                column("total", "orders", ColumnClause::Select),
+                column("id", "users", ColumnClause::JoinOn),
+                column("user_id", "orders", ColumnClause::JoinOn),
+            ]
+        );
+    }
+
+    #[test]
+    fn cte_columns() {
+        let src = "WITH recent AS (SELECT id FROM orders) SELECT r.id FROM recent r;";
+        let found = extract_columns(&parse(src), src, PositionEncoding::Utf8);
+        assert_eq!(found.len(), 2);
+
+        let inner = found[0].table.as_ref().unwrap();
+        assert_eq!(inner.name, "orders");
+        assert!(!inner.is_cte);
+
+        let outer = found[1].table.as_ref().unwrap();
+        assert_eq!(outer.name, "recent");
+        assert!(outer.is_cte);
+    }
+
+    #[test]
+    fn subquery_alias_shadows_table() {
+        let src = "SELECT users.id FROM (SELECT id FROM accounts) AS users;";
+        let found = extract_columns(&parse(src), src, PositionEncoding::Utf8);
+        assert_eq!(found.len(), 2);
+
+        let outer = found[0].table.as_ref().unwrap();
+        assert_eq!(outer.name, "users");
+        assert!(outer.is_subquery);
+
+        let inner = found[1].table.as_ref().unwrap();
+        assert_eq!(inner.name, "accounts");
+        assert!(!inner.is_subquery);
+    }
+
+    #[test]
+    fn insert_targets_only_the_column_list() {
+        let insert_columns = |src| {
+            columns(src)
+                .into_iter()
+                .filter(|(_, _, clause)| *clause == ColumnClause::Insert)
+                .map(|(name, _, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            insert_columns("INSERT INTO users (id, name) VALUES (1, 'a') RETURNING id;"),
+            vec!["id", "name"]
+        );
+        assert_eq!(
+            columns("INSERT INTO logs (a) SELECT b FROM events;"),
+            vec![
+                column("a", "logs", ColumnClause::Insert),
+                column("b", "events", ColumnClause::Select),
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+
+use super::format::FormatOptions;
+use super::lint::{LintConfig, rule_ids};
+use super::position::PositionEncoding;
+use super::tree_output::OutputFormat;
+
+/// 作業ディレクトリから自動で読み込む設定ファイル
+pub const DEFAULT_CONFIG_FILE: &str = ".sqlmcp.toml";
+
+/// サーバーの設定
+/// すべての項目は省略でき、省略した項目は既定値になる
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// parse_sql などで output_format を省略したときの形式
+    pub output_format: OutputFormat,
+    /// 結果の列番号の単位
+    pub position_encoding: PositionEncoding,
+    /// SQL の方言
+    pub dialect: Dialect,
+    pub limits: Limits,
+    /// lint_sql のルールの設定
+    pub lint: LintConfig,
+    /// format_sql でオプションを省略したときの整形の設定
+    pub format: FormatOptions,
+    /// 変更を監視するディレクトリの設定
+    pub watch: WatchConfig,
+}
+
+/// SQL の方言
+/// future-architect/tree-sitter-sql の文法は PostgreSQL のみを対象としている
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    #[default]
+    Postgresql,
+}
+
+impl Dialect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Postgresql => "postgresql",
+        }
+    }
+}
+
+/// 入力の大きさの上限
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// 1 回のツール呼び出しで受け付ける SQL の最大バイト数
+    pub max_sql_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_sql_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// ディレクトリの監視の設定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// 監視するディレクトリ。相対パスは作業ディレクトリからのパス
+    pub dirs: Vec<PathBuf>,
+    /// 続けて起きた変更をまとめる時間 (ミリ秒)
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            debounce_ms: 200,
+        }
+    }
+}
+
+impl Config {
+    /// 設定ファイルを読み込む
+    /// path を省略した場合は作業ディレクトリの .sqlmcp.toml を探し、なければ既定の設定を返す
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                if !path.exists() {
+                    return Ok(Self::default());
+                }
+                path
+            }
+        };
+
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+
+        Self::parse(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// 設定ファイルの内容を解釈する
+    /// エラーには行番号とキーを含める
+    pub fn parse(text: &str) -> Result<Self> {
+        match toml::from_str::<Self>(text) {
+            Ok(config) => {
+                config.validate(text)?;
+                Ok(config)
+            }
+            Err(e) => {
+                let message = e.message();
+                let Some(span) = e.span() else {
+                    bail!("{}", message);
+                };
+                let line = text[..span.start].matches('\n').count() + 1;
+                match key_at(text, span.start) {
+                    Some(key) => bail!("line {}, key `{}`: {}", line, key, message),
+                    None => bail!("line {}: {}", line, message),
+                }
+            }
+        }
+    }
+
+    /// 型だけでは検査できない項目を検査する
+    fn validate(&self, text: &str) -> Result<()> {
+        let known = rule_ids();
+        let mut unknown: Vec<_> = self
+            .lint
+            .rules
+            .keys()
+            .filter(|id| !known.contains(&id.as_str()))
+            .collect();
+        unknown.sort();
+        if let Some(id) = unknown.first() {
+            let message = format!(
+                "key `lint.rules.{}`: unknown lint rule, expected one of {}",
+                id,
+                known.join(", ")
+            );
+            match line_of_key(text, id) {
+                Some(line) => bail!("line {}, {}", line, message),
+                None => bail!("{}", message),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// key が定義されている行の番号 (1 始まり)
+fn line_of_key(text: &str, key: &str) -> Option<usize> {
+    text.lines()
+        .position(|line| {
+            line.split_once('=').is_some_and(|(k, _)| {
+                let k = k.trim();
+                k == key || k.trim_matches('"') == key || k.ends_with(&format!(".{}", key))
+            })
+        })
+        .map(|index| index + 1)
+}
+
+/// offset の行に書かれたキーを、所属するテーブル名を含めて返す (例: `limits.max_sql_bytes`)
+fn key_at(text: &str, offset: usize) -> Option<String> {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[line_start..].lines().next().unwrap_or("");
+    let key = line.split_once('=').map(|(key, _)| key.trim())?;
+
+    let table = text[..line_start].lines().rev().find_map(|line| {
+        let line = line.trim();
+        line.strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+            .map(|table| table.trim_matches(['[', ']']).trim().to_string())
+    });
+
+    match table {
+        Some(table) => Some(format!("{}.{}", table, key)),
+        None => Some(key.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        format!("{:#}", Config::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn valid_config() {
+        let config = Config::parse(
+            r#"
+position_encoding = "utf-16"
+
+[limits]
+max_sql_bytes = 1024
+
+[lint.rules]
+select-star = "off"
+
+[watch]
+dirs = ["sql"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.position_encoding, PositionEncoding::Utf16);
+        assert_eq!(config.limits.max_sql_bytes, 1024);
+        assert_eq!(config.lint.rules.len(), 1);
+        assert_eq!(config.watch.dirs, vec![PathBuf::from("sql")]);
+        assert_eq!(config.watch.debounce_ms, 200);
+    }
+
+    #[test]
+    fn type_error_names_line_and_key() {
+        let error = error("[limits]\nmax_sql_bytes = \"large\"\n");
+        assert!(
+            error.starts_with("line 2, key `limits.max_sql_bytes`: "),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn unknown_key_names_line_and_key() {
+        let error = error("output_format = \"json\"\n\n[format]\nindent = 4\n");
+        assert!(
+            error.starts_with("line 4, key `format.indent`: "),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn unknown_lint_rule_names_line_and_key() {
+        let error = error("[lint.rules]\nselect-star = \"off\"\nno-such-rule = \"warning\"\n");
+        assert!(
+            error.starts_with("line 3, key `lint.rules.no-such-rule`: unknown lint rule"),
+            "{}",
+            error
+        );
+        assert!(error.contains("select-star"), "{}", error);
+    }
+}
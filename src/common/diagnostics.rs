@@ -0,0 +1,281 @@
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange, point_at};
+
+/// ERROR ノードのテキストをこの文字数で切り詰める
+const MAX_TEXT_CHARS: usize = 100;
+
+/// 構文エラーの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticKind {
+    /// パーサが解釈できなかった範囲 (ERROR ノード)
+    Error,
+    /// パーサが補ったトークン (MISSING ノード)
+    Missing,
+}
+
+/// 構文エラー 1 件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+    /// エラー範囲のソーステキスト
+    pub text: String,
+    /// エラーを囲むノードの種類
+    pub parent_kind: Option<&'static str>,
+    /// MISSING ノードから分かる、本来必要だったトークン
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+}
+
+/// ツリーから ERROR ノードと MISSING ノードを集める
+/// 位置の列は encoding の単位で数える
+pub fn collect_diagnostics(tree: &Tree, src: &str, encoding: PositionEncoding) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    visit(tree.root_node(), src, encoding, &mut diagnostics);
+
+    diagnostics
+}
+
+fn visit(node: Node, src: &str, encoding: PositionEncoding, diagnostics: &mut Vec<Diagnostic>) {
+    if node.is_missing() {
+        diagnostics.push(missing_diagnostic(node, src, encoding));
+        return;
+    }
+
+    if node.is_error() {
+        diagnostics.push(error_diagnostic(node, src, encoding));
+        // ERROR の内側の MISSING は別途報告する
+        report_missing_inside(node, src, encoding, diagnostics);
+        return;
+    }
+
+    // エラーを含まない部分木は走査しない
+    if !node.has_error() {
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(child, src, encoding, diagnostics);
+    }
+}
+
+fn report_missing_inside(
+    node: Node,
+    src: &str,
+    encoding: PositionEncoding,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.is_missing() {
+            diagnostics.push(missing_diagnostic(child, src, encoding));
+        } else if child.has_error() {
+            report_missing_inside(child, src, encoding, diagnostics);
+        }
+    }
+}
+
+fn error_diagnostic(node: Node, src: &str, encoding: PositionEncoding) -> Diagnostic {
+    let text = truncate(&src[node.byte_range()]);
+    let message = if text.is_empty() {
+        "syntax error".to_string()
+    } else {
+        format!("syntax error near \"{}\"", text)
+    };
+
+    Diagnostic {
+        kind: DiagnosticKind::Error,
+        message,
+        range: SourceRange::new(node, src, encoding),
+        text,
+        parent_kind: enclosing_kind(node),
+        expected: None,
+    }
+}
+
+fn missing_diagnostic(node: Node, src: &str, encoding: PositionEncoding) -> Diagnostic {
+    let expected = node.kind().to_string();
+
+    Diagnostic {
+        kind: DiagnosticKind::Missing,
+        message: format!("missing \"{}\"", expected),
+        range: SourceRange::new(node, src, encoding),
+        text: String::new(),
+        parent_kind: enclosing_kind(node),
+        expected: Some(expected),
+    }
+}
+
+/// ERROR 以外で最も近い祖先ノードの種類
+fn enclosing_kind(node: Node) -> Option<&'static str> {
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        if !p.is_error() {
+            return Some(p.kind());
+        }
+        parent = p.parent();
+    }
+    None
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_TEXT_CHARS) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+/// コードフレームに表示する最大行数 (これを超える行は省略する)
+const MAX_FRAME_LINES: usize = 5;
+
+/// rustc のように、エラー箇所のソース行と下線を並べた文字列を返す
+/// `-->` の後の列番号は診断の位置のとおりに表示する
+pub fn render_diagnostics(src: &str, diagnostics: &[Diagnostic]) -> String {
+    let lines: Vec<&str> = src
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+
+    diagnostics
+        .iter()
+        .map(|diagnostic| render_diagnostic(src, &lines, diagnostic))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_diagnostic(src: &str, lines: &[&str], diagnostic: &Diagnostic) -> String {
+    let start_row = diagnostic.range.start.line - 1;
+    let end_row = (diagnostic.range.end.line - 1).min(lines.len().saturating_sub(1));
+    // 行末で終わるエラーは次の行の先頭を end とするので、その行は表示しない
+    let end_row = if end_row > start_row && diagnostic.range.end.column == 1 {
+        end_row - 1
+    } else {
+        end_row
+    };
+    let shown_end_row = end_row.min(start_row + MAX_FRAME_LINES - 1);
+    let gutter = (shown_end_row + 1).to_string().len();
+
+    let mut result = format!("error: {}\n", diagnostic.message);
+    result.push_str(&format!(
+        "{:gutter$}--> {}:{}\n",
+        "", diagnostic.range.start.line, diagnostic.range.start.column
+    ));
+    result.push_str(&format!("{:gutter$} |\n", ""));
+
+    // 下線はバイト単位の列で引く
+    let start_column = point_at(src, diagnostic.range.start_byte).column;
+    let end_column = point_at(src, diagnostic.range.end_byte).column;
+    for row in start_row..=shown_end_row {
+        let line = lines.get(row).copied().unwrap_or("");
+        let from = if row == start_row { start_column } else { 0 };
+        let to = if row == end_row && diagnostic.range.end.line - 1 == row {
+            end_column
+        } else {
+            line.len()
+        };
+
+        result.push_str(&format!("{:>gutter$} | {}\n", row + 1, line));
+        result.push_str(&format!("{:gutter$} | {}\n", "", underline(line, from, to)));
+    }
+
+    if shown_end_row < end_row {
+        result.push_str(&format!("{:gutter$} | ...\n", ""));
+    }
+
+    result
+}
+
+/// line の [from, to) バイトの範囲の下に ^ を引く
+/// 幅 0 の範囲 (MISSING) でも 1 文字分の ^ を表示する
+fn underline(line: &str, from: usize, to: usize) -> String {
+    let from = floor_char_boundary(line, from.min(line.len()));
+    let to = floor_char_boundary(line, to.min(line.len())).max(from);
+
+    // タブはそのまま残し、表示位置を揃える
+    let mut result: String = line[..from]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = line[from..to].chars().count().max(1);
+    result.push_str(&"^".repeat(width));
+
+    result
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(
+        src: &str,
+        start_byte: usize,
+        end_byte: usize,
+        encoding: PositionEncoding,
+    ) -> Diagnostic {
+        Diagnostic {
+            kind: DiagnosticKind::Error,
+            message: "unexpected".to_string(),
+            range: SourceRange::from_bytes(src, start_byte, end_byte, encoding),
+            text: src[start_byte..end_byte].to_string(),
+            parent_kind: None,
+            expected: None,
+        }
+    }
+
+    #[test]
+    fn header_column_follows_encoding() {
+        // "é" は UTF-8 で 2 バイト、UTF-16 で 1 コードユニット
+        let src = "SELECT 'é' @";
+        let header = |encoding| {
+            render_diagnostics(src, &[diagnostic(src, 12, 13, encoding)])
+                .lines()
+                .nth(1)
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+        assert_eq!(header(PositionEncoding::Utf8), "--> 1:13");
+        assert_eq!(header(PositionEncoding::Utf16), "--> 1:12");
+        assert_eq!(header(PositionEncoding::Utf32), "--> 1:12");
+    }
+
+    #[test]
+    fn underlines_error_range() {
+        let src = "SELECT 1\nFROM @@";
+        let rendered = render_diagnostics(src, &[diagnostic(src, 14, 16, PositionEncoding::Utf8)]);
+        assert_eq!(
+            rendered,
+            "error: unexpected\n --> 2:6\n  |\n2 | FROM @@\n  |      ^^\n"
+        );
+    }
+
+    #[test]
+    fn underline_does_not_depend_on_encoding() {
+        let src = "SELECT 'é' @@";
+        let render = |encoding| render_diagnostics(src, &[diagnostic(src, 12, 14, encoding)]);
+        assert!(render(PositionEncoding::Utf8).ends_with("|            ^^\n"));
+        assert_eq!(
+            render(PositionEncoding::Utf16)
+                .lines()
+                .skip(3)
+                .collect::<Vec<_>>(),
+            render(PositionEncoding::Utf8)
+                .lines()
+                .skip(3)
+                .collect::<Vec<_>>()
+        );
+    }
+}
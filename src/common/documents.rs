@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{InputEdit, Tree};
+
+use super::position::point_at;
+use super::tree_sitter_sql::{parse, reparse};
+
+/// ドキュメントに対するテキストの変更 1 件
+/// start_byte..old_end_byte を new_text で置き換える (tree-sitter の InputEdit に相当)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TextChange {
+    /// 変更の開始位置 (バイト)
+    pub start_byte: usize,
+    /// 置き換える範囲の終了位置 (変更前のテキストでのバイト位置)
+    pub old_end_byte: usize,
+    /// 置き換え後のテキスト
+    pub new_text: String,
+}
+
+/// 開いている SQL ドキュメント
+pub struct Document {
+    pub text: String,
+    pub tree: Tree,
+    /// 開いたときは 1 で、編集のたびに増える
+    pub version: u64,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let tree = parse(&text);
+        Self {
+            text,
+            tree,
+            version: 1,
+        }
+    }
+
+    /// 変更を順に適用し、前のツリーを再利用してパースし直す
+    /// 変更の位置は、それより前の変更を適用した後のテキストでの位置
+    fn edit(&mut self, changes: &[TextChange], max_bytes: usize) -> Result<(), String> {
+        let mut text = self.text.clone();
+        let mut tree = self.tree.clone();
+
+        for (i, change) in changes.iter().enumerate() {
+            let TextChange {
+                start_byte,
+                old_end_byte,
+                ref new_text,
+            } = *change;
+            if start_byte > old_end_byte
+                || old_end_byte > text.len()
+                || !text.is_char_boundary(start_byte)
+                || !text.is_char_boundary(old_end_byte)
+            {
+                return Err(format!(
+                    "change {}: invalid range {}..{} for a document of {} bytes",
+                    i,
+                    start_byte,
+                    old_end_byte,
+                    text.len()
+                ));
+            }
+
+            let start_position = point_at(&text, start_byte);
+            let old_end_position = point_at(&text, old_end_byte);
+            text.replace_range(start_byte..old_end_byte, new_text);
+            let new_end_byte = start_byte + new_text.len();
+
+            tree.edit(&InputEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position: point_at(&text, new_end_byte),
+            });
+        }
+
+        if text.len() > max_bytes {
+            return Err(format!(
+                "document is too large after the edit: {} bytes (limit is {} bytes)",
+                text.len(),
+                max_bytes
+            ));
+        }
+
+        self.tree = reparse(&text, &tree);
+        self.text = text;
+        self.version += 1;
+        Ok(())
+    }
+}
+
+/// 開いているドキュメントの一覧
+#[derive(Default)]
+pub struct Documents {
+    next_id: u64,
+    documents: HashMap<String, Document>,
+}
+
+impl Documents {
+    /// ドキュメントを開き、その ID を返す
+    pub fn open(&mut self, text: String) -> String {
+        self.next_id += 1;
+        let id = self.next_id.to_string();
+        self.documents.insert(id.clone(), Document::new(text));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Document> {
+        self.documents.get(id)
+    }
+
+    /// 開いているドキュメントの ID (開いた順)
+    pub fn ids(&self) -> Vec<&str> {
+        let mut ids: Vec<_> = self.documents.keys().map(String::as_str).collect();
+        ids.sort_by_key(|id| id.parse::<u64>().unwrap_or(u64::MAX));
+        ids
+    }
+
+    /// ドキュメントを編集する。ID が不明な場合や変更の範囲が不正な場合はエラー
+    /// エラーの場合、ドキュメントは変更しない
+    pub fn edit(
+        &mut self,
+        id: &str,
+        changes: &[TextChange],
+        max_bytes: usize,
+    ) -> Result<&Document, String> {
+        let document = self
+            .documents
+            .get_mut(id)
+            .ok_or_else(|| format!("unknown document id: {}", id))?;
+        document.edit(changes, max_bytes)?;
+        Ok(document)
+    }
+
+    /// ドキュメントを閉じる。開いていなかった場合は false
+    pub fn close(&mut self, id: &str) -> bool {
+        self.documents.remove(id).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(start_byte: usize, old_end_byte: usize, new_text: &str) -> TextChange {
+        TextChange {
+            start_byte,
+            old_end_byte,
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn sequential_changes_apply_to_the_edited_text() {
+        let mut documents = Documents::default();
+        let id = documents.open("SELECT a FROM t;".to_string());
+
+        // 2 件目の位置は 1 件目を適用した後のテキストでの位置
+        let document = documents
+            .edit(&id, &[change(7, 8, "a, b"), change(17, 18, "users")], 1024)
+            .unwrap();
+        assert_eq!(document.text, "SELECT a, b FROM users;");
+        assert_eq!(document.version, 2);
+        assert_eq!(
+            document.tree.root_node().to_sexp(),
+            parse(&document.text).root_node().to_sexp()
+        );
+
+        let document = documents
+            .edit(&id, &[change(0, 0, "-- c\n")], 1024)
+            .unwrap();
+        assert_eq!(document.text, "-- c\nSELECT a, b FROM users;");
+        assert_eq!(document.version, 3);
+    }
+
+    #[test]
+    fn invalid_change_rejects_the_whole_batch() {
+        let mut documents = Documents::default();
+        let id = documents.open("SELECT é;".to_string());
+
+        for changes in [
+            vec![change(0, 6, "select"), change(8, 100, "")],
+            vec![change(0, 6, "select"), change(5, 4, "")],
+            vec![change(0, 6, "select"), change(8, 9, "")],
+        ] {
+            assert!(documents.edit(&id, &changes, 1024).is_err());
+            let document = documents.get(&id).unwrap();
+            assert_eq!(document.text, "SELECT é;");
+            assert_eq!(document.version, 1);
+        }
+    }
+
+    #[test]
+    fn batch_over_max_bytes_is_rejected() {
+        let mut documents = Documents::default();
+        let id = documents.open("SELECT 1;".to_string());
+
+        // 途中で上限を超えても、最後に収まっていれば受け付ける
+        let changes = [change(7, 8, &"1".repeat(20)), change(7, 27, "2")];
+        assert_eq!(documents.edit(&id, &changes, 10).unwrap().text, "SELECT 2;");
+
+        let error = documents
+            .edit(&id, &[change(7, 8, "12345")], 10)
+            .err()
+            .unwrap();
+        assert!(error.contains("too large"), "{}", error);
+        assert_eq!(documents.get(&id).unwrap().text, "SELECT 2;");
+        assert_eq!(documents.get(&id).unwrap().version, 2);
+    }
+
+    #[test]
+    fn unknown_and_closed_documents() {
+        let mut documents = Documents::default();
+        let first = documents.open("SELECT 1;".to_string());
+        let second = documents.open("SELECT 2;".to_string());
+        assert_eq!(documents.ids(), vec![first.as_str(), second.as_str()]);
+
+        assert!(documents.close(&first));
+        assert!(!documents.close(&first));
+        assert!(documents.edit(&first, &[], 1024).is_err());
+        assert_eq!(documents.ids(), vec![second.as_str()]);
+    }
+}
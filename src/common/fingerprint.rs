@@ -0,0 +1,180 @@
+use serde::Serialize;
+use tree_sitter::Node;
+
+use super::minify::leaf_tokens;
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::statement_spans;
+use super::syntax::{is_keyword_token, is_literal, node_text};
+
+/// リテラルを置き換えるプレースホルダ
+const PLACEHOLDER: &str = "?";
+
+/// 文 1 つの指紋
+#[derive(Debug, Clone, Serialize)]
+pub struct Fingerprint {
+    pub index: usize,
+    /// normalized の FNV-1a 64 ビットハッシュ (16 進数)
+    pub fingerprint: String,
+    /// リテラルをプレースホルダにし、空白とキーワードの大文字・小文字を揃えた文
+    pub normalized: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// スクリプトの文ごとに、リテラルの値によらない指紋を求める
+/// 同じ形の問い合わせは、リテラルの値や空白、コメント、キーワードの書き方が違っても同じ指紋になる
+pub fn fingerprint(src: &str, encoding: PositionEncoding) -> Vec<Fingerprint> {
+    statement_spans(src)
+        .into_iter()
+        .enumerate()
+        .map(|(index, span)| {
+            let tree = span.parse(src);
+            let normalized = normalize(tree.root_node(), src);
+
+            Fingerprint {
+                index,
+                fingerprint: format!("{:016x}", fnv1a(&normalized)),
+                normalized,
+                range: SourceRange::from_bytes(src, span.start, span.end, encoding),
+            }
+        })
+        .collect()
+}
+
+fn normalize(root: Node, src: &str) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut in_list_start: Option<usize> = None;
+
+    for leaf in leaf_tokens(root, src) {
+        let text = node_text(leaf, src);
+        if text == ";" {
+            continue;
+        }
+
+        let token = if is_literal(leaf) {
+            PLACEHOLDER.to_string()
+        } else if is_keyword_token(leaf, src) {
+            text.split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_uppercase()
+        } else {
+            text.to_string()
+        };
+
+        // `IN (1, 2, 3)` はリストの長さによらず `IN (?)` にする
+        match token.as_str() {
+            "(" if tokens.last().is_some_and(|last| last == "IN") => {
+                in_list_start = Some(tokens.len() + 1);
+            }
+            ")" => {
+                if let Some(start) = in_list_start.take()
+                    && (tokens.len() - start) % 2 == 1
+                    && tokens[start..]
+                        .iter()
+                        .enumerate()
+                        .all(|(i, token)| token == if i % 2 == 0 { PLACEHOLDER } else { "," })
+                {
+                    tokens.truncate(start + 1);
+                }
+            }
+            _ => {}
+        }
+        tokens.push(token);
+    }
+
+    join(&tokens)
+}
+
+/// 字句を空白 1 つで繋ぐ (括弧の内側、カンマとドットの前後は詰める)
+fn join(tokens: &[String]) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&str> = None;
+    for token in tokens {
+        if let Some(previous) = previous
+            && !matches!(token.as_str(), "," | ")" | ".")
+            && !matches!(previous, "(" | ".")
+        {
+            out.push(' ');
+        }
+        out.push_str(token);
+        previous = Some(token);
+    }
+    out
+}
+
+/// FNV-1a (64 ビット)。実行環境によらず同じ値になる
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprints(src: &str) -> Vec<String> {
+        fingerprint(src, PositionEncoding::Utf8)
+            .into_iter()
+            .map(|fingerprint| fingerprint.fingerprint)
+            .collect()
+    }
+
+    fn normalized(src: &str) -> String {
+        fingerprint(src, PositionEncoding::Utf8)
+            .remove(0)
+            .normalized
+    }
+
+    #[test]
+    fn ignores_literal_values() {
+        assert_eq!(
+            normalized("SELECT a FROM t WHERE id = 42 AND name = 'x';"),
+            "SELECT a FROM t WHERE id = ? AND name = ?"
+        );
+        assert_eq!(
+            fingerprints("SELECT a FROM t WHERE id = 1;"),
+            fingerprints("SELECT a FROM t WHERE id = 2;")
+        );
+    }
+
+    #[test]
+    fn collapses_in_lists() {
+        assert_eq!(
+            normalized("SELECT a FROM t WHERE id IN (1, 2, 3);"),
+            "SELECT a FROM t WHERE id IN (?)"
+        );
+        assert_eq!(
+            fingerprints("SELECT a FROM t WHERE id IN (1);"),
+            fingerprints("SELECT a FROM t WHERE id IN (1, 2, 3);")
+        );
+    }
+
+    #[test]
+    fn ignores_whitespace_comments_and_keyword_case() {
+        assert_eq!(
+            fingerprints("SELECT a FROM t WHERE id = 1;"),
+            fingerprints("select a\n  -- comment\n  from t /* c */ where id = 1;")
+        );
+    }
+
+    #[test]
+    fn distinguishes_tables_and_columns() {
+        let base = fingerprints("SELECT a FROM t WHERE id = 1;");
+        assert_ne!(base, fingerprints("SELECT a FROM u WHERE id = 1;"));
+        assert_ne!(base, fingerprints("SELECT b FROM t WHERE id = 1;"));
+        assert_ne!(base, fingerprints("SELECT a FROM t WHERE uid = 1;"));
+    }
+
+    #[test]
+    fn one_fingerprint_per_statement() {
+        let found = fingerprints("SELECT 1; SELECT a FROM t; SELECT 2;");
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0], found[2]);
+        assert_ne!(found[0], found[1]);
+    }
+}
@@ -0,0 +1,385 @@
+use std::ops::Range;
+
+use serde::Serialize;
+
+use super::diagnostics::{Diagnostic, DiagnosticKind, collect_diagnostics};
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::{StatementSpan, split_statements, statement_spans, tokens};
+
+/// 文の先頭になるキーワード
+/// UPDATE の SET や INSERT の VALUES のように、文の途中の行頭にも現れるものは含めない
+const STATEMENT_KEYWORDS: &[&str] = &[
+    "SELECT", "INSERT", "UPDATE", "DELETE", "MERGE", "WITH", "CREATE", "ALTER", "DROP", "TRUNCATE",
+    "GRANT", "REVOKE", "BEGIN", "COMMIT", "ROLLBACK", "COPY", "EXPLAIN",
+];
+
+/// 直後の行が同じ文の続きになるトークン
+const CONTINUATION_TOKENS: &[&str] = &[
+    "(",
+    ",",
+    "AS",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+    "ALL",
+    "DISTINCT",
+    "EXISTS",
+    "IN",
+    "THEN",
+    "ELSE",
+];
+
+/// 修正の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixKind {
+    /// 閉じていない括弧、または余分な閉じ括弧
+    UnbalancedParenthesis,
+    /// FROM や `)` の直前の余分なカンマ
+    TrailingComma,
+    /// 文と文の間の `;` の欠落
+    MissingTerminator,
+}
+
+/// 元の SQL に対するテキストの編集 1 件
+/// range を replacement で置き換える (挿入の場合 range は空)
+#[derive(Debug, Clone, Serialize)]
+pub struct TextEdit {
+    pub kind: FixKind,
+    pub message: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+    pub replacement: String,
+}
+
+/// 修正の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct Fix {
+    /// 元の SQL に対する編集 (位置の順)
+    pub edits: Vec<TextEdit>,
+    /// 編集を適用した SQL
+    pub fixed_sql: String,
+    /// fixed_sql が構文エラーなくパースできるか
+    pub valid: bool,
+    /// fixed_sql に残っている構文エラー (位置は fixed_sql での位置)
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// 推測なしに直せる構文エラーを探し、修正を返す
+/// 修正は構文エラーのある文ごとに考え、候補を位置の順に試して、
+/// 新しい構文エラーを生まずに構文エラーを減らすものだけを採用する
+/// 編集と残った構文エラーの位置の列は encoding の単位で数える
+pub fn fix(src: &str, encoding: PositionEncoding) -> Fix {
+    let mut edits = Vec::new();
+
+    for span in statement_spans(src) {
+        // 区切りの `;` も含めて、編集の前後を同じ範囲でパースする
+        let range = span.start
+            ..span
+                .terminator
+                .map_or(span.end, |terminator| terminator + 1);
+        let mut errors = syntax_errors(src, range.clone(), &[]);
+        if errors.is_empty() {
+            continue;
+        }
+
+        let mut accepted: Vec<TextEdit> = Vec::new();
+        for candidate in candidates(src, span, encoding) {
+            let mut tried = accepted.clone();
+            tried.push(candidate);
+            let tried_errors = syntax_errors(src, range.clone(), &tried);
+            if improves(&errors, &tried_errors) {
+                errors = tried_errors;
+                accepted = tried;
+            }
+            if errors.is_empty() {
+                break;
+            }
+        }
+        edits.extend(accepted);
+    }
+
+    let fixed_sql = apply(src, 0..src.len(), &edits);
+    let diagnostics: Vec<_> = split_statements(&fixed_sql, encoding)
+        .into_iter()
+        .flat_map(|statement| statement.diagnostics)
+        .collect();
+
+    Fix {
+        edits,
+        fixed_sql,
+        valid: diagnostics.is_empty(),
+        diagnostics,
+    }
+}
+
+impl TextEdit {
+    fn new(
+        src: &str,
+        encoding: PositionEncoding,
+        range: Range<usize>,
+        kind: FixKind,
+        message: &str,
+        replacement: &str,
+    ) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+            range: SourceRange::from_bytes(src, range.start, range.end, encoding),
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+/// 文 1 つの修正の候補
+fn candidates(src: &str, span: StatementSpan, encoding: PositionEncoding) -> Vec<TextEdit> {
+    let tokens = tokens(src, span.start, span.end);
+    let text = |token: &Range<usize>| &src[token.clone()];
+    let mut candidates = Vec::new();
+
+    // 末尾の余分な閉じ括弧と、閉じていない括弧
+    let mut depth: isize = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match text(token) {
+            "(" => depth += 1,
+            ")" if depth == 0 => {
+                if tokens[i..].iter().all(|token| text(token) == ")") {
+                    for token in &tokens[i..] {
+                        candidates.push(TextEdit::new(
+                            src,
+                            encoding,
+                            token.clone(),
+                            FixKind::UnbalancedParenthesis,
+                            "remove unmatched `)`",
+                            "",
+                        ));
+                    }
+                }
+                break;
+            }
+            ")" => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        candidates.push(TextEdit::new(
+            src,
+            encoding,
+            span.end..span.end,
+            FixKind::UnbalancedParenthesis,
+            "close unclosed `(`",
+            &")".repeat(depth as usize),
+        ));
+    }
+
+    // FROM、`)`、文末の直前の余分なカンマ
+    for (i, token) in tokens.iter().enumerate() {
+        if text(token) != "," {
+            continue;
+        }
+        let trailing = match tokens.get(i + 1) {
+            None => true,
+            Some(next) => text(next) == ")" || text(next).eq_ignore_ascii_case("FROM"),
+        };
+        if trailing {
+            candidates.push(TextEdit::new(
+                src,
+                encoding,
+                token.clone(),
+                FixKind::TrailingComma,
+                "remove trailing comma",
+                "",
+            ));
+        }
+    }
+
+    // 行頭から新しい文が始まっているのに `;` がない
+    let mut depth: isize = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match text(token) {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ => {}
+        }
+        let Some(previous) = i.checked_sub(1).map(|i| &tokens[i]) else {
+            continue;
+        };
+        let starts_line = src[previous.end..token.start].contains('\n');
+        let starts_statement = STATEMENT_KEYWORDS
+            .iter()
+            .any(|keyword| text(token).eq_ignore_ascii_case(keyword));
+        let continues = CONTINUATION_TOKENS
+            .iter()
+            .any(|continuation| text(previous).eq_ignore_ascii_case(continuation));
+        if depth == 0 && starts_line && starts_statement && !continues {
+            candidates.push(TextEdit::new(
+                src,
+                encoding,
+                previous.end..previous.end,
+                FixKind::MissingTerminator,
+                "insert `;` between statements",
+                ";",
+            ));
+        }
+    }
+
+    candidates.sort_by_key(|edit| (edit.range.start_byte, edit.range.end_byte));
+    candidates
+}
+
+/// src の range の部分に編集を適用した文字列
+fn apply(src: &str, range: Range<usize>, edits: &[TextEdit]) -> String {
+    let mut result = String::new();
+    let mut offset = range.start;
+    for edit in edits {
+        result.push_str(&src[offset..edit.range.start_byte]);
+        result.push_str(&edit.replacement);
+        offset = edit.range.end_byte;
+    }
+    result.push_str(&src[offset..range.end]);
+    result
+}
+
+/// 修正を試すときに比べる構文エラー (位置は編集前の SQL でのバイト位置)
+struct SyntaxError {
+    kind: DiagnosticKind,
+    range: Range<usize>,
+}
+
+/// src の range の部分に編集を適用し、文ごとにパースし直したときの構文エラー
+fn syntax_errors(src: &str, range: Range<usize>, edits: &[TextEdit]) -> Vec<SyntaxError> {
+    let text = apply(src, range.clone(), edits);
+    statement_spans(&text)
+        .into_iter()
+        .flat_map(|span| collect_diagnostics(&span.parse(&text), &text, PositionEncoding::Utf8))
+        .map(|diagnostic| SyntaxError {
+            kind: diagnostic.kind,
+            range: original_offset(range.start, edits, diagnostic.range.start_byte)
+                ..original_offset(range.start, edits, diagnostic.range.end_byte),
+        })
+        .collect()
+}
+
+/// 編集を適用したテキストでのバイト位置を、編集前の SQL でのバイト位置に戻す
+/// start は編集を適用した範囲の先頭。置き換えたテキストの中の位置は、編集の範囲の先頭にする
+fn original_offset(start: usize, edits: &[TextEdit], offset: usize) -> usize {
+    let mut original = start;
+    let mut edited = 0;
+    for edit in edits {
+        let unchanged = edit.range.start_byte - original;
+        if offset < edited + unchanged {
+            break;
+        }
+        edited += unchanged;
+        if offset < edited + edit.replacement.len() {
+            return edit.range.start_byte;
+        }
+        edited += edit.replacement.len();
+        original = edit.range.end_byte;
+    }
+    original + (offset - edited)
+}
+
+/// 修正後の構文エラーが、修正前のどれかと同じ種類で位置が重なり、かつ数が減っているか
+/// 数が減っても、修正前になかった構文エラーが現れる場合は採用しない
+fn improves(before: &[SyntaxError], after: &[SyntaxError]) -> bool {
+    after.len() < before.len()
+        && after.iter().all(|error| {
+            before.iter().any(|known| {
+                known.kind == error.kind
+                    && known.range.start <= error.range.end
+                    && error.range.start <= known.range.end
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(fix: &Fix) -> Vec<FixKind> {
+        fix.edits.iter().map(|edit| edit.kind).collect()
+    }
+
+    #[test]
+    fn removes_trailing_comma() {
+        let fix = fix("SELECT a, b, FROM t;", PositionEncoding::Utf8);
+        assert_eq!(fix.fixed_sql, "SELECT a, b FROM t;");
+        assert_eq!(kinds(&fix), vec![FixKind::TrailingComma]);
+        assert!(fix.valid);
+    }
+
+    #[test]
+    fn closes_unclosed_parenthesis() {
+        let fix = fix("SELECT (1 + 2;", PositionEncoding::Utf8);
+        assert_eq!(fix.fixed_sql, "SELECT (1 + 2);");
+        assert_eq!(kinds(&fix), vec![FixKind::UnbalancedParenthesis]);
+        assert!(fix.valid);
+    }
+
+    #[test]
+    fn inserts_missing_terminator() {
+        let fix = fix("SELECT 1\nSELECT 2;", PositionEncoding::Utf8);
+        assert_eq!(fix.fixed_sql, "SELECT 1;\nSELECT 2;");
+        assert_eq!(kinds(&fix), vec![FixKind::MissingTerminator]);
+        assert!(fix.valid);
+    }
+
+    #[test]
+    fn rejects_candidate_that_adds_errors() {
+        // SELECT の前に `;` を入れる候補は INSERT を壊すので採用しない
+        let fix = fix("INSERT INTO t\nSELECT a, FROM s;", PositionEncoding::Utf8);
+        assert_eq!(fix.fixed_sql, "INSERT INTO t\nSELECT a FROM s;");
+        assert_eq!(kinds(&fix), vec![FixKind::TrailingComma]);
+        assert!(fix.valid);
+    }
+
+    #[test]
+    fn repairs_only_what_it_can() {
+        let fix = fix("SELECT a, FROM t;\nSELECT 1 +;", PositionEncoding::Utf8);
+        assert_eq!(fix.fixed_sql, "SELECT a FROM t;\nSELECT 1 +;");
+        assert_eq!(kinds(&fix), vec![FixKind::TrailingComma]);
+        assert!(!fix.valid);
+        assert!(!fix.diagnostics.is_empty());
+        assert!(
+            fix.diagnostics
+                .iter()
+                .all(|diagnostic| diagnostic.range.start.line == 2)
+        );
+    }
+
+    #[test]
+    fn maps_offsets_back_through_edits() {
+        let src = "SELECT a, FROM t;";
+        let edits = [TextEdit::new(
+            src,
+            PositionEncoding::Utf8,
+            8..9,
+            FixKind::TrailingComma,
+            "remove trailing comma",
+            "",
+        )];
+        // 編集後の "SELECT a FROM t;" での位置
+        assert_eq!(original_offset(0, &edits, 7), 7);
+        assert_eq!(original_offset(0, &edits, 9), 10);
+        assert_eq!(original_offset(0, &edits, 16), 17);
+
+        let edits = [TextEdit::new(
+            src,
+            PositionEncoding::Utf8,
+            17..17,
+            FixKind::MissingTerminator,
+            "insert",
+            ";;",
+        )];
+        assert_eq!(original_offset(10, &edits, 7), 17);
+        assert_eq!(original_offset(10, &edits, 8), 17);
+    }
+
+    #[test]
+    fn leaves_valid_sql_alone() {
+        let fix = fix("SELECT 1;\nSELECT 2;", PositionEncoding::Utf8);
+        assert!(fix.edits.is_empty());
+        assert!(fix.valid);
+    }
+}
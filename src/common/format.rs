@@ -0,0 +1,817 @@
+use std::ops::Range;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::statement_spans;
+use super::syntax::{is_keyword, is_keyword_token, node_text};
+use super::tree_sitter_sql::parse;
+
+/// キーワードの大文字・小文字
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LetterCase {
+    #[default]
+    Upper,
+    Lower,
+    /// 元の SQL のまま
+    Preserve,
+}
+
+/// 折り返した一覧のカンマの位置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CommaStyle {
+    /// 行末にカンマを置く
+    #[default]
+    Last,
+    /// 行頭にカンマを置く
+    First,
+}
+
+/// 整形のオプション (設定ファイルの `[format]`)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatOptions {
+    /// 字下げ 1 段の空白の数
+    pub indent_width: usize,
+    pub keyword_case: LetterCase,
+    pub comma_style: CommaStyle,
+    /// これを超える句は要素ごとに折り返す
+    pub line_width: usize,
+    /// true の場合、句 (FROM、WHERE など) を常に行頭から始める
+    /// false の場合、文が 1 行に収まらないときだけ句ごとに改行する
+    pub clause_per_line: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 2,
+            keyword_case: LetterCase::Upper,
+            comma_style: CommaStyle::Last,
+            line_width: 80,
+            clause_per_line: true,
+        }
+    }
+}
+
+/// ツリーをもとに SQL を整形する
+/// 字句の間の空白だけを変え、字句そのものはキーワードの大文字・小文字以外変えない
+pub fn format(tree: &Tree, src: &str, options: &FormatOptions) -> String {
+    let mut writer = Writer {
+        src,
+        options,
+        out: String::new(),
+        line_indent: 0,
+        pending_newline: None,
+        blank_line: false,
+        previous: None,
+        paren_depth: 0,
+        statements: Vec::new(),
+        breaks: Vec::new(),
+    };
+    writer.visit(tree.root_node());
+
+    let mut out = writer.out.trim_end().to_string();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// 整形を断った理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    /// 元の SQL に構文エラーがある
+    SyntaxError,
+    /// 整形結果が元と同じ構造にパースされない
+    StructureChanged,
+    /// 整形結果をもう一度整形すると変わる
+    Unstable,
+}
+
+impl FormatError {
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::SyntaxError => "statement has syntax errors",
+            Self::StructureChanged => "formatting changed the syntax tree",
+            Self::Unstable => "formatting again would change the statement",
+        }
+    }
+}
+
+/// 構文エラーのない SQL だけを整形し、整形結果が元と同じ構造にパースされることを確かめる
+pub fn format_checked(
+    tree: &Tree,
+    src: &str,
+    options: &FormatOptions,
+) -> Result<String, FormatError> {
+    if tree.root_node().has_error() {
+        return Err(FormatError::SyntaxError);
+    }
+    let formatted = format(tree, src, options);
+    if same_structure(
+        tree.root_node(),
+        src,
+        parse(&formatted).root_node(),
+        &formatted,
+    ) {
+        Ok(formatted)
+    } else {
+        Err(FormatError::StructureChanged)
+    }
+}
+
+/// 範囲整形での文 1 つの結果
+#[derive(Debug, Clone, Serialize)]
+pub struct FormattedStatement {
+    pub index: usize,
+    /// 元の SQL での文の範囲 (区切りの `;` を含む)
+    #[serde(flatten)]
+    pub range: SourceRange,
+    pub changed: bool,
+    /// 整形しなかった理由
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<&'static str>,
+}
+
+/// 範囲整形の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeFormat {
+    pub changed: bool,
+    pub formatted_sql: String,
+    /// range と重なった文
+    pub statements: Vec<FormattedStatement>,
+    /// 元の SQL からの unified diff
+    pub diff: String,
+}
+
+/// range と重なるトップレベルの文だけを整形する
+/// 文の外側 (文の間の空白やコメント) と range と重ならない文はそのまま残す
+/// 文の範囲の列は encoding の単位で数える
+pub fn format_range(
+    src: &str,
+    range: Range<usize>,
+    options: &FormatOptions,
+    encoding: PositionEncoding,
+) -> RangeFormat {
+    let mut statements = Vec::new();
+    let mut replacements: Vec<Replacement> = Vec::new();
+
+    for (index, span) in statement_spans(src).into_iter().enumerate() {
+        let end = span
+            .terminator
+            .map_or(span.end, |terminator| terminator + 1);
+        // 空の範囲はカーソル位置として、その位置を含む文を整形する
+        let overlaps = if range.is_empty() {
+            span.start <= range.start && range.start <= end
+        } else {
+            span.start < range.end && range.start < end
+        };
+        if !overlaps {
+            continue;
+        }
+
+        let text = &src[span.start..end];
+        // 整形し直しても変わらない場合だけ採用し、範囲整形を繰り返しても差分が出ないようにする
+        let formatted = format_checked(&parse(text), text, options).and_then(|formatted| {
+            let formatted = formatted.trim_end();
+            match format_checked(&parse(formatted), formatted, options) {
+                Ok(again) if again.trim_end() == formatted => Ok(formatted.to_string()),
+                _ => Err(FormatError::Unstable),
+            }
+        });
+
+        let changed = formatted.as_ref().is_ok_and(|formatted| formatted != text);
+        statements.push(FormattedStatement {
+            index,
+            range: SourceRange::from_bytes(src, span.start, end, encoding),
+            changed,
+            skipped: formatted.as_ref().err().map(|e| e.message()),
+        });
+        if let Ok(formatted) = formatted
+            && changed
+        {
+            replacements.push((span.start..end, formatted));
+        }
+    }
+
+    let mut formatted_sql = String::new();
+    let mut offset = 0;
+    for (range, replacement) in &replacements {
+        formatted_sql.push_str(&src[offset..range.start]);
+        formatted_sql.push_str(replacement);
+        offset = range.end;
+    }
+    formatted_sql.push_str(&src[offset..]);
+
+    RangeFormat {
+        changed: !replacements.is_empty(),
+        diff: unified_diff(src, &replacements),
+        formatted_sql,
+        statements,
+    }
+}
+
+/// 元の SQL のバイト範囲と、それを置き換える文字列
+type Replacement = (Range<usize>, String);
+
+/// 置き換えを行単位の unified diff にする
+/// 同じ行にかかる置き換えは 1 つのハンクにまとめる
+fn unified_diff(src: &str, replacements: &[Replacement]) -> String {
+    if replacements.is_empty() {
+        return String::new();
+    }
+
+    // 置き換えを含む行全体の範囲ごとにまとめる
+    let mut hunks: Vec<(Range<usize>, Vec<&Replacement>)> = Vec::new();
+    for replacement in replacements {
+        let start = src[..replacement.0.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = src[replacement.0.end..]
+            .find('\n')
+            .map_or(src.len(), |i| replacement.0.end + i);
+        match hunks.last_mut() {
+            Some((lines, members)) if start <= lines.end => {
+                lines.end = end;
+                members.push(replacement);
+            }
+            _ => hunks.push((start..end, vec![replacement])),
+        }
+    }
+
+    let mut diff = String::from("--- original\n+++ formatted\n");
+    let mut delta: isize = 0;
+    for (lines, members) in hunks {
+        let old = &src[lines.clone()];
+        let mut new = String::new();
+        let mut offset = lines.start;
+        for (range, replacement) in members {
+            new.push_str(&src[offset..range.start]);
+            new.push_str(replacement);
+            offset = range.end;
+        }
+        new.push_str(&src[offset..lines.end]);
+
+        let old_start = src[..lines.start].matches('\n').count() + 1;
+        let old_count = old.lines().count().max(1);
+        let new_count = new.lines().count().max(1);
+        let new_start = old_start as isize + delta;
+        delta += new_count as isize - old_count as isize;
+
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        ));
+        for line in old.lines() {
+            diff.push_str(&format!("-{}\n", line));
+        }
+        for line in new.lines() {
+            diff.push_str(&format!("+{}\n", line));
+        }
+    }
+    diff
+}
+
+/// 2 つのツリーが空白とキーワードの大文字・小文字を除いて同じか
+pub fn same_structure(a: Node, a_src: &str, b: Node, b_src: &str) -> bool {
+    compare_trees(a, a_src, b, b_src, false)
+}
+
+/// same_structure と同じだが、コメントの有無も無視する
+pub fn same_structure_without_comments(a: Node, a_src: &str, b: Node, b_src: &str) -> bool {
+    compare_trees(a, a_src, b, b_src, true)
+}
+
+fn compare_trees(a: Node, a_src: &str, b: Node, b_src: &str, skip_comments: bool) -> bool {
+    if a.kind() != b.kind() || a.is_named() != b.is_named() {
+        return false;
+    }
+    if a.child_count() == 0 && b.child_count() == 0 {
+        let (a_text, b_text) = (node_text(a, a_src), node_text(b, b_src));
+        return if is_keyword_token(a, a_src) {
+            normalize_keyword(a_text) == normalize_keyword(b_text)
+        } else {
+            a_text.trim_end() == b_text.trim_end()
+        };
+    }
+
+    let (a_children, b_children) = (
+        compared_children(a, skip_comments),
+        compared_children(b, skip_comments),
+    );
+    a_children.len() == b_children.len()
+        && a_children
+            .into_iter()
+            .zip(b_children)
+            .all(|(a, b)| compare_trees(a, a_src, b, b_src, skip_comments))
+}
+
+fn compared_children(node: Node, skip_comments: bool) -> Vec<Node> {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .filter(|child| !(skip_comments && child.kind().contains("comment")))
+        .collect()
+}
+
+fn normalize_keyword(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+/// 整形中の文
+struct StatementLayout {
+    start_byte: usize,
+    /// 句を書き始める字下げの段数
+    indent: usize,
+    /// 句ごとに改行するか
+    break_clauses: bool,
+}
+
+/// 1 行に収まらず、要素ごとに折り返している句
+struct Break {
+    /// 句が属する文 (statements の長さ)
+    statement: usize,
+    /// 句の始まりでの括弧の深さ。これと同じ深さのカンマや AND / OR で折り返す
+    paren_depth: usize,
+    /// 折り返した要素の字下げの段数
+    indent: usize,
+    /// 句の先頭のキーワードの後でまだ改行していない
+    head_pending: bool,
+}
+
+/// 直前に書いた字句
+#[derive(Clone, Copy)]
+struct Previous<'a> {
+    text: &'a str,
+    end_byte: usize,
+    is_comment: bool,
+}
+
+struct Writer<'a> {
+    src: &'a str,
+    options: &'a FormatOptions,
+    out: String,
+    /// 現在の行の字下げの段数
+    line_indent: usize,
+    /// 次の字句の前で改行する場合、その行の字下げの段数
+    pending_newline: Option<usize>,
+    /// 次の改行で空行を入れるか
+    blank_line: bool,
+    previous: Option<Previous<'a>>,
+    paren_depth: usize,
+    statements: Vec<StatementLayout>,
+    breaks: Vec<Break>,
+}
+
+impl<'a> Writer<'a> {
+    fn visit(&mut self, node: Node) {
+        if node.child_count() == 0 {
+            self.leaf(node);
+        } else if node.kind().ends_with("_statement") {
+            self.statement(node);
+        } else if is_clause(node) {
+            self.clause(node);
+        } else {
+            self.children(node);
+        }
+    }
+
+    fn children(&mut self, node: Node) {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.visit(child);
+        }
+    }
+
+    fn statement(&mut self, node: Node) {
+        let top_level = self.statements.is_empty();
+        let opened = self.previous.is_some_and(|previous| previous.text == "(");
+        let outer_indent = self.line_indent;
+
+        let indent = if opened {
+            self.newline(outer_indent + 1);
+            outer_indent + 1
+        } else if top_level {
+            // `;` のない文の区切り
+            if self.previous.is_some() && self.pending_newline.is_none() {
+                self.newline(0);
+                self.blank_line = true;
+            }
+            0
+        } else {
+            self.statements
+                .last()
+                .map_or(outer_indent, |statement| statement.indent)
+        };
+
+        let break_clauses = self.options.clause_per_line || !self.fits(flat_width(node, self.src));
+        self.statements.push(StatementLayout {
+            start_byte: node.start_byte(),
+            indent,
+            break_clauses,
+        });
+        self.children(node);
+        self.statements.pop();
+
+        if opened {
+            self.newline(outer_indent);
+        }
+    }
+
+    fn clause(&mut self, node: Node) {
+        let Some(statement) = self.statements.last() else {
+            self.children(node);
+            return;
+        };
+        let break_clauses = statement.break_clauses;
+        if break_clauses && node.start_byte() != statement.start_byte {
+            self.newline(statement.indent);
+        }
+
+        // 改行して書く入れ子の句は幅に数えない
+        let width = if break_clauses {
+            clause_head_width(node, self.src)
+        } else {
+            flat_width(node, self.src)
+        };
+        if self.fits(width) {
+            self.children(node);
+            return;
+        }
+
+        let indent = self.pending_newline.unwrap_or(self.line_indent) + 1;
+        self.breaks.push(Break {
+            statement: self.statements.len(),
+            paren_depth: self.paren_depth,
+            indent,
+            // JOIN は結合先の表をキーワードと同じ行に書く
+            head_pending: !node.kind().contains("join"),
+        });
+        self.children(node);
+        self.breaks.pop();
+    }
+
+    fn leaf(&mut self, node: Node) {
+        let text = node_text(node, self.src);
+        if text.is_empty() {
+            return;
+        }
+        let is_comment = node.kind().contains("comment");
+        let keyword = is_keyword_token(node, self.src);
+
+        if is_comment {
+            self.comment(node, text);
+            return;
+        }
+
+        if text == ")" {
+            self.paren_depth = self.paren_depth.saturating_sub(1);
+        }
+        self.break_before(node, text, keyword);
+
+        let text = if keyword {
+            match self.options.keyword_case {
+                LetterCase::Upper => text.to_uppercase(),
+                LetterCase::Lower => text.to_lowercase(),
+                LetterCase::Preserve => text.to_string(),
+            }
+        } else {
+            text.to_string()
+        };
+        self.write(node, &text, false);
+
+        match node_text(node, self.src) {
+            "(" => self.paren_depth += 1,
+            "," => self.break_after_comma(),
+            ";" if self.statements.is_empty() => {
+                self.newline(0);
+                self.blank_line = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn comment(&mut self, node: Node, text: &str) {
+        let line_start = self.src[..node.start_byte()]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let own_line = self.src[line_start..node.start_byte()].trim().is_empty();
+
+        // 行末のコメントは改行を待っていても前の行に書く
+        let pending = self.pending_newline;
+        if own_line {
+            if pending.is_none() && self.previous.is_some() {
+                self.newline(self.line_indent);
+            }
+        } else {
+            self.pending_newline = None;
+        }
+        self.write(node, text.trim_end(), true);
+        if !own_line {
+            self.pending_newline = pending;
+        }
+
+        if text.starts_with("--") {
+            self.newline(self.pending_newline.unwrap_or(self.line_indent));
+        }
+    }
+
+    /// 折り返している句の中で、要素の前の改行
+    fn break_before(&mut self, node: Node, text: &str, keyword: bool) {
+        let statements = self.statements.len();
+        let comma_style = self.options.comma_style;
+        let Some(current) = self
+            .breaks
+            .last_mut()
+            .filter(|current| current.statement == statements)
+        else {
+            return;
+        };
+
+        if current.head_pending && !keyword {
+            current.head_pending = false;
+            let indent = current.indent;
+            self.newline(indent);
+            return;
+        }
+        if current.paren_depth != self.paren_depth {
+            return;
+        }
+        let indent = current.indent;
+        if (text == "," && comma_style == CommaStyle::First)
+            || is_keyword(node, "AND")
+            || is_keyword(node, "OR")
+        {
+            self.newline(indent);
+        }
+    }
+
+    fn break_after_comma(&mut self) {
+        let statements = self.statements.len();
+        if self.options.comma_style == CommaStyle::Last
+            && let Some(current) = self.breaks.last().filter(|current| {
+                current.statement == statements && current.paren_depth == self.paren_depth
+            })
+        {
+            self.newline(current.indent);
+        }
+    }
+
+    /// 次の字句の前で改行する
+    fn newline(&mut self, indent: usize) {
+        self.pending_newline = Some(indent);
+    }
+
+    /// 現在の行に width 文字を書き足しても行の幅に収まるか
+    fn fits(&self, width: usize) -> bool {
+        let column = match self.pending_newline {
+            Some(indent) => indent * self.options.indent_width,
+            None => self.out[self.out.rfind('\n').map_or(0, |i| i + 1)..]
+                .chars()
+                .count(),
+        };
+        column + width <= self.options.line_width
+    }
+
+    fn write(&mut self, node: Node, text: &str, is_comment: bool) {
+        if let Some(indent) = self.pending_newline.take() {
+            if !self.out.is_empty() {
+                self.out.truncate(self.out.trim_end_matches(' ').len());
+                self.out.push('\n');
+                if self.blank_line {
+                    self.out.push('\n');
+                }
+                self.out
+                    .push_str(&" ".repeat(indent * self.options.indent_width));
+            }
+            self.line_indent = indent;
+            self.blank_line = false;
+        } else if self.needs_space(node, text) {
+            self.out.push(' ');
+        }
+
+        self.out.push_str(text);
+        self.previous = Some(Previous {
+            text: node_text(node, self.src),
+            end_byte: node.end_byte(),
+            is_comment,
+        });
+    }
+
+    /// 直前の字句との間に空白を入れるか
+    fn needs_space(&self, node: Node, text: &str) -> bool {
+        let Some(previous) = self.previous else {
+            return false;
+        };
+        if self.out.is_empty() || self.out.ends_with('\n') || self.out.ends_with(' ') {
+            return false;
+        }
+        if matches!(text, "," | ";" | ")" | ".") || matches!(previous.text, "(" | ".") {
+            return false;
+        }
+        if previous.is_comment || previous.text == "," {
+            return true;
+        }
+        // それ以外は元の SQL に空白があった場合だけ空白を入れる
+        self.src[previous.end_byte.min(node.start_byte())..node.start_byte()]
+            .chars()
+            .any(char::is_whitespace)
+    }
+}
+
+/// 行頭から書く句か
+/// 関数呼び出しやウィンドウ定義などの括弧の中の句 (`OVER (ORDER BY ...)`) は除く
+fn is_clause(node: Node) -> bool {
+    if !node.is_named() || !node.kind().ends_with("_clause") {
+        return false;
+    }
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        if p.kind().ends_with("_statement") {
+            return true;
+        }
+        let mut cursor = p.walk();
+        let parenthesized = !p.kind().ends_with("_clause")
+            && p.children(&mut cursor)
+                .any(|child| !child.is_named() && child.kind() == "(");
+        if parenthesized {
+            return false;
+        }
+        parent = p.parent();
+    }
+    false
+}
+
+/// 空白を 1 つにまとめて 1 行に書いた場合の幅
+fn flat_width(node: Node, src: &str) -> usize {
+    collapsed_width(node_text(node, src))
+}
+
+/// 入れ子の句を除いた、句の先頭部分の幅
+fn clause_head_width(node: Node, src: &str) -> usize {
+    let mut end = node.end_byte();
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if is_clause(child) {
+            end = child.start_byte();
+            break;
+        }
+    }
+    collapsed_width(&src[node.start_byte()..end])
+}
+
+fn collapsed_width(text: &str) -> usize {
+    let words: Vec<_> = text.split_whitespace().collect();
+    words.iter().map(|word| word.chars().count()).sum::<usize>() + words.len().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 整形し、結果が元と同じ構造にパースされ、もう一度整形しても変わらないことを確かめる
+    fn check(src: &str, options: &FormatOptions) -> String {
+        let formatted = format_checked(&parse(src), src, options).unwrap();
+        let again = format_checked(&parse(&formatted), &formatted, options).unwrap();
+        assert_eq!(again, formatted, "not idempotent: {}", src);
+        formatted
+    }
+
+    fn options() -> FormatOptions {
+        FormatOptions::default()
+    }
+
+    #[test]
+    fn one_clause_per_line() {
+        assert_eq!(
+            check("select a, b from t where a = 1;", &options()),
+            "SELECT a, b\nFROM t\nWHERE a = 1;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_statements_apart() {
+        let formatted = check("select 1; select 2;", &options());
+        assert_eq!(formatted, "SELECT 1;\n\nSELECT 2;\n");
+    }
+
+    #[test]
+    fn keeps_line_comments() {
+        let src = "-- head\nselect a, -- first\n  b\nfrom t; -- tail\n";
+        let formatted = check(src, &options());
+        let lines: Vec<_> = formatted.lines().collect();
+        assert_eq!(lines[0], "-- head");
+        // 行末のコメントは同じ行に残り、その後で改行する
+        assert!(lines.iter().any(|line| line.ends_with("a, -- first")));
+        assert!(lines.iter().any(|line| line.ends_with("; -- tail")));
+    }
+
+    #[test]
+    fn keeps_block_comments() {
+        let src = "select a /* trailing */\n/* own line */\nfrom t;";
+        let formatted = check(src, &options());
+        assert!(formatted.contains("a /* trailing */"));
+        assert!(
+            formatted
+                .lines()
+                .any(|line| line.trim() == "/* own line */")
+        );
+    }
+
+    #[test]
+    fn keyword_case() {
+        let src = "Select a From t;";
+        let with_case = |keyword_case| FormatOptions {
+            keyword_case,
+            ..options()
+        };
+        assert_eq!(
+            check(src, &with_case(LetterCase::Upper)),
+            "SELECT a\nFROM t;\n"
+        );
+        assert_eq!(
+            check(src, &with_case(LetterCase::Lower)),
+            "select a\nfrom t;\n"
+        );
+        assert_eq!(
+            check(src, &with_case(LetterCase::Preserve)),
+            "Select a\nFrom t;\n"
+        );
+    }
+
+    #[test]
+    fn wraps_long_clauses() {
+        let src = "SELECT alpha, beta, gamma FROM t;";
+        let narrow = |comma_style| FormatOptions {
+            line_width: 16,
+            comma_style,
+            ..options()
+        };
+
+        let last = check(src, &narrow(CommaStyle::Last));
+        assert_eq!(last, "SELECT\n  alpha,\n  beta,\n  gamma\nFROM t;\n");
+
+        let first = check(src, &narrow(CommaStyle::First));
+        assert_eq!(first, "SELECT\n  alpha\n  , beta\n  , gamma\nFROM t;\n");
+
+        // 幅に収まる句は折り返さない
+        assert_eq!(
+            check(src, &options()),
+            "SELECT alpha, beta, gamma\nFROM t;\n"
+        );
+    }
+
+    #[test]
+    fn format_range_is_idempotent() {
+        let src = "select 1;\n\n  -- keep   \nselect a,b from t where a=1 ;  select 2;\n";
+        for range in [0..0, 30..30, 0..src.len()] {
+            let once = format_range(src, range.clone(), &options(), PositionEncoding::Utf8);
+            let twice = format_range(
+                &once.formatted_sql,
+                range,
+                &options(),
+                PositionEncoding::Utf8,
+            );
+            assert!(!twice.changed, "{:?}", twice.statements);
+            assert_eq!(twice.formatted_sql, once.formatted_sql);
+        }
+    }
+
+    #[test]
+    fn format_range_keeps_bytes_outside_statements() {
+        let src = "select 1;\n\n  -- keep   \nselect a,b from t where a=1 ;  select 2;\n";
+        let start = src.find("select a").unwrap();
+        let end = src.rfind("  select 2").unwrap();
+        let result = format_range(
+            src,
+            start + 1..start + 2,
+            &options(),
+            PositionEncoding::Utf8,
+        );
+
+        assert!(result.changed);
+        assert_eq!(result.statements.len(), 1);
+        assert_eq!(result.statements[0].range.start_byte, start);
+        assert_eq!(result.statements[0].range.end_byte, end);
+        assert!(result.formatted_sql.starts_with(&src[..start]));
+        assert!(result.formatted_sql.ends_with(&src[end..]));
+        assert_eq!(
+            &result.formatted_sql[start..result.formatted_sql.len() - (src.len() - end)],
+            "SELECT a, b\nFROM t\nWHERE a = 1;"
+        );
+    }
+
+    #[test]
+    fn refuses_sql_with_errors() {
+        let src = "SELECT a, FROM WHERE;";
+        assert_eq!(
+            format_checked(&parse(src), src, &options()),
+            Err(FormatError::SyntaxError)
+        );
+    }
+}
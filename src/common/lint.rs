@@ -0,0 +1,169 @@
+mod safety;
+mod style;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::statement_spans;
+
+/// 指摘の重要度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// 設定ファイルでのルールの重要度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    /// ルールを無効にする
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+/// キーワードの大文字・小文字の規約
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+    /// 文の最初のキーワードに揃える
+    #[default]
+    Consistent,
+}
+
+/// 設定ファイルの `[lint]`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    pub keyword_case: KeywordCase,
+    /// ルール ID ごとの重要度 (例: `select-star = "off"`)
+    pub rules: HashMap<String, RuleLevel>,
+}
+
+impl LintConfig {
+    /// ルールの重要度。無効にされている場合は None
+    fn severity(&self, rule: &dyn Rule) -> Option<Severity> {
+        match self.rules.get(rule.id()) {
+            None => Some(rule.default_severity()),
+            Some(RuleLevel::Off) => None,
+            Some(RuleLevel::Info) => Some(Severity::Info),
+            Some(RuleLevel::Warning) => Some(Severity::Warning),
+            Some(RuleLevel::Error) => Some(Severity::Error),
+        }
+    }
+}
+
+/// リントの指摘 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub rule_id: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// リントのルール
+/// ツリーのすべてのノードについて check が呼ばれる
+pub trait Rule {
+    /// ルールを識別する ID (例: `delete-without-where`)
+    fn id(&self) -> &'static str;
+
+    fn default_severity(&self) -> Severity;
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter);
+}
+
+/// ルールが指摘を報告する先
+pub struct Reporter<'a> {
+    rule: &'a dyn Rule,
+    severity: Severity,
+    src: &'a str,
+    encoding: PositionEncoding,
+    findings: &'a mut Vec<Finding>,
+}
+
+impl Reporter<'_> {
+    pub fn report(&mut self, node: Node, message: impl Into<String>) {
+        self.findings.push(Finding {
+            rule_id: self.rule.id(),
+            severity: self.severity,
+            message: message.into(),
+            range: SourceRange::new(node, self.src, self.encoding),
+        });
+    }
+}
+
+/// 組み込みのルールの一覧
+pub fn rules(config: &LintConfig) -> Vec<Box<dyn Rule>> {
+    let mut rules = safety::rules();
+    rules.extend(style::rules(config));
+    rules
+}
+
+/// 組み込みのルールの ID の一覧
+pub fn rule_ids() -> Vec<&'static str> {
+    rules(&LintConfig::default())
+        .iter()
+        .map(|rule| rule.id())
+        .collect()
+}
+
+/// スクリプトの各文に、設定で有効なすべてのルールを適用する
+/// 指摘の位置の列は encoding の単位で数える
+pub fn lint(src: &str, config: &LintConfig, encoding: PositionEncoding) -> Vec<Finding> {
+    let rules: Vec<_> = rules(config)
+        .into_iter()
+        .filter_map(|rule| {
+            config
+                .severity(rule.as_ref())
+                .map(|severity| (rule, severity))
+        })
+        .collect();
+    let mut findings = Vec::new();
+
+    for span in statement_spans(src) {
+        let tree = span.parse(src);
+        let mut cursor = tree.walk();
+        loop {
+            let node = cursor.node();
+            for (rule, severity) in &rules {
+                let mut reporter = Reporter {
+                    rule: rule.as_ref(),
+                    severity: *severity,
+                    src,
+                    encoding,
+                    findings: &mut findings,
+                };
+                rule.check(node, src, &mut reporter);
+            }
+
+            if cursor.goto_first_child() || cursor.goto_next_sibling() {
+                continue;
+            }
+            let finished = loop {
+                if !cursor.goto_parent() {
+                    break true;
+                }
+                if cursor.goto_next_sibling() {
+                    break false;
+                }
+            };
+            if finished {
+                break;
+            }
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.range.start_byte);
+    findings
+}
@@ -0,0 +1,299 @@
+//! 破壊的な操作や意図しない全件処理を検出するルール
+
+use tree_sitter::Node;
+
+use super::{Reporter, Rule, Severity};
+use crate::common::syntax::{child_of_kind, from_items, has_keyword, is_keyword, node_text};
+
+pub fn rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(MissingWhere {
+            id: "delete-without-where",
+            statement_kind: "delete_statement",
+            verb: "DELETE",
+        }),
+        Box::new(MissingWhere {
+            id: "update-without-where",
+            statement_kind: "update_statement",
+            verb: "UPDATE",
+        }),
+        Box::new(DestructiveStatement {
+            id: "drop-statement",
+            statement_kind: "drop_statement",
+            verb: "DROP",
+        }),
+        Box::new(DestructiveStatement {
+            id: "truncate-statement",
+            statement_kind: "truncate_statement",
+            verb: "TRUNCATE",
+        }),
+        Box::new(TautologicalCondition),
+        Box::new(CartesianJoin),
+    ]
+}
+
+/// WHERE 句のない UPDATE / DELETE
+struct MissingWhere {
+    id: &'static str,
+    statement_kind: &'static str,
+    verb: &'static str,
+}
+
+impl Rule for MissingWhere {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, node: Node, _src: &str, report: &mut Reporter) {
+        if node.kind() == self.statement_kind && child_of_kind(node, "where_clause").is_none() {
+            report.report(
+                node,
+                format!("{} without a WHERE clause affects every row", self.verb),
+            );
+        }
+    }
+}
+
+/// DROP / TRUNCATE
+struct DestructiveStatement {
+    id: &'static str,
+    statement_kind: &'static str,
+    verb: &'static str,
+}
+
+impl Rule for DestructiveStatement {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, _src: &str, report: &mut Reporter) {
+        // 文のノードがない文法でも、先頭のキーワードで判定する
+        let is_statement = node.kind() == self.statement_kind
+            || (node
+                .parent()
+                .is_some_and(|parent| parent.parent().is_none())
+                && node.kind().ends_with("_statement")
+                && node
+                    .child(0)
+                    .is_some_and(|first| is_keyword(first, self.verb)));
+        if is_statement {
+            report.report(node, format!("{} permanently removes data", self.verb));
+        }
+    }
+}
+
+/// `WHERE 1 = 1` や `OR 'a' = 'a'` のように常に真になる条件
+struct TautologicalCondition;
+
+impl Rule for TautologicalCondition {
+    fn id(&self) -> &'static str {
+        "tautological-condition"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        if !is_inside_condition(node) {
+            return;
+        }
+
+        if is_keyword(node, "TRUE") && node.parent().is_some_and(is_condition_clause) {
+            report.report(node, "condition is always true");
+            return;
+        }
+
+        if node.kind() != "binary_expression" {
+            return;
+        }
+        let (Some(left), Some(operator), Some(right)) = binary_operands(node) else {
+            return;
+        };
+        let operator = node_text(operator, src);
+        if !matches!(operator, "=" | ">=" | "<=") {
+            return;
+        }
+        if normalize(node_text(left, src)) == normalize(node_text(right, src)) {
+            report.report(
+                node,
+                format!(
+                    "condition `{}` is always true",
+                    node_text(node, src)
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            );
+        }
+    }
+}
+
+fn is_condition_clause(node: Node) -> bool {
+    matches!(
+        node.kind(),
+        "where_clause" | "having_clause" | "join_clause"
+    )
+}
+
+fn is_inside_condition(node: Node) -> bool {
+    let mut parent = node.parent();
+    while let Some(p) = parent {
+        if is_condition_clause(p) {
+            return true;
+        }
+        if p.kind().ends_with("_statement") {
+            return false;
+        }
+        parent = p.parent();
+    }
+    false
+}
+
+/// 二項演算の左辺、演算子、右辺
+fn binary_operands(node: Node) -> (Option<Node>, Option<Node>, Option<Node>) {
+    let left = node
+        .child_by_field_name("left")
+        .or_else(|| node.named_child(0));
+    let right = node
+        .child_by_field_name("right")
+        .or_else(|| node.named_child(node.named_child_count().saturating_sub(1)));
+    let operator = node.child_by_field_name("operator").or_else(|| {
+        let mut cursor = node.walk();
+        node.children(&mut cursor).find(|child| !child.is_named())
+    });
+    (left, operator, right)
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// 結合条件のない JOIN、または WHERE 句のないカンマ区切りの FROM
+struct CartesianJoin;
+
+impl Rule for CartesianJoin {
+    fn id(&self) -> &'static str {
+        "cartesian-join"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, _src: &str, report: &mut Reporter) {
+        match node.kind() {
+            "join_clause" => {
+                let intentional = has_keyword(node, "CROSS") || has_keyword(node, "NATURAL");
+                let has_condition = has_keyword(node, "ON") || has_keyword(node, "USING");
+                if !intentional && !has_condition {
+                    report.report(
+                        node,
+                        "JOIN without an ON condition produces a cartesian product",
+                    );
+                }
+            }
+            "from_clause" => {
+                let tables = from_items(node).tables.len();
+                let has_where = node
+                    .parent()
+                    .is_some_and(|parent| child_of_kind(parent, "where_clause").is_some());
+                if tables > 1 && !has_where {
+                    report.report(
+                        node,
+                        "comma-separated tables without a WHERE clause produce a cartesian product",
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::lint::{LintConfig, lint};
+    use crate::common::position::PositionEncoding;
+
+    fn findings(src: &str, rule_id: &str) -> usize {
+        lint(src, &LintConfig::default(), PositionEncoding::Utf8)
+            .iter()
+            .filter(|finding| finding.rule_id == rule_id)
+            .count()
+    }
+
+    #[test]
+    fn update_and_delete_without_where() {
+        assert_eq!(
+            findings("UPDATE users SET active = false;", "update-without-where"),
+            1
+        );
+        assert_eq!(
+            findings(
+                "UPDATE users SET active = false WHERE id = 1;",
+                "update-without-where"
+            ),
+            0
+        );
+        assert_eq!(findings("DELETE FROM users;", "delete-without-where"), 1);
+        assert_eq!(
+            findings("DELETE FROM users WHERE id = 1;", "delete-without-where"),
+            0
+        );
+    }
+
+    #[test]
+    fn tautological_conditions() {
+        for src in [
+            "SELECT * FROM t WHERE 1 = 1;",
+            "SELECT * FROM t WHERE TRUE;",
+            "DELETE FROM t WHERE 'a' = 'a';",
+            "SELECT * FROM t WHERE a = 2 OR 1=1;",
+        ] {
+            assert_eq!(findings(src, "tautological-condition"), 1, "{}", src);
+        }
+        for src in [
+            "SELECT * FROM t WHERE a = 1;",
+            "SELECT 1 = 1 FROM t;",
+            "SELECT * FROM t WHERE a = b;",
+        ] {
+            assert_eq!(findings(src, "tautological-condition"), 0, "{}", src);
+        }
+    }
+
+    #[test]
+    fn destructive_statements() {
+        assert_eq!(findings("DROP TABLE users;", "drop-statement"), 1);
+        assert_eq!(findings("TRUNCATE users;", "truncate-statement"), 1);
+        assert_eq!(findings("SELECT * FROM users;", "drop-statement"), 0);
+        assert_eq!(findings("SELECT * FROM users;", "truncate-statement"), 0);
+    }
+
+    #[test]
+    fn cartesian_joins() {
+        assert_eq!(findings("SELECT * FROM a, b;", "cartesian-join"), 1);
+        assert_eq!(
+            findings("SELECT * FROM a, b WHERE a.id = b.a_id;", "cartesian-join"),
+            0
+        );
+        assert_eq!(
+            findings("SELECT * FROM a JOIN b ON a.id = b.a_id;", "cartesian-join"),
+            0
+        );
+        assert_eq!(
+            findings("SELECT * FROM a CROSS JOIN b;", "cartesian-join"),
+            0
+        );
+    }
+}
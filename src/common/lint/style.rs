@@ -0,0 +1,519 @@
+//! スタイルガイドに沿った書き方を求めるルール
+
+use std::collections::HashSet;
+
+use tree_sitter::Node;
+
+use super::{KeywordCase, LintConfig, Reporter, Rule, Severity};
+use crate::common::syntax::{
+    child_of_kind, from_items, has_keyword, is_keyword, is_keyword_token, is_name, node_text,
+    normalize_identifier, split_qualified_name,
+};
+
+pub fn rules(config: &LintConfig) -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(KeywordCaseRule {
+            case: config.keyword_case,
+        }),
+        Box::new(SelectStar),
+        Box::new(ImplicitAlias),
+        Box::new(PositionalGroupBy),
+        Box::new(MixedJoinStyle),
+        Box::new(UnqualifiedColumn),
+    ]
+}
+
+/// キーワードの大文字・小文字を揃える
+struct KeywordCaseRule {
+    case: KeywordCase,
+}
+
+impl Rule for KeywordCaseRule {
+    fn id(&self) -> &'static str {
+        "keyword-case"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        if !is_keyword_token(node, src) {
+            return;
+        }
+
+        let text = node_text(node, src);
+        let upper = match self.case {
+            KeywordCase::Upper => true,
+            KeywordCase::Lower => false,
+            KeywordCase::Consistent => {
+                let Some(first) = first_keyword(statement_of(node), src) else {
+                    return;
+                };
+                node_text(first, src) != node_text(first, src).to_lowercase()
+            }
+        };
+
+        let expected = if upper {
+            text.to_uppercase()
+        } else {
+            text.to_lowercase()
+        };
+        if text != expected {
+            report.report(
+                node,
+                format!("keyword `{}` should be written as `{}`", text, expected),
+            );
+        }
+    }
+}
+
+/// ノードを含むトップレベルの文
+fn statement_of(node: Node) -> Node {
+    let mut node = node;
+    while let Some(parent) = node.parent() {
+        if parent.parent().is_none() {
+            return node;
+        }
+        node = parent;
+    }
+    node
+}
+
+fn first_keyword<'a>(node: Node<'a>, src: &str) -> Option<Node<'a>> {
+    if is_keyword_token(node, src) {
+        return Some(node);
+    }
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    children
+        .into_iter()
+        .find_map(|child| first_keyword(child, src))
+}
+
+/// EXISTS の中以外での `SELECT *`
+struct SelectStar;
+
+impl Rule for SelectStar {
+    fn id(&self) -> &'static str {
+        "select-star"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        if node.child_count() != 0 || node_text(node, src) != "*" {
+            return;
+        }
+
+        // a * b や count(*) の * は対象外
+        let mut parent = node.parent();
+        while let Some(p) = parent {
+            match p.kind() {
+                "select_clause" => break,
+                "binary_expression" | "function_call" => return,
+                kind if kind.ends_with("_statement") => return,
+                _ => parent = p.parent(),
+            }
+        }
+        let Some(select_clause) = parent else {
+            return;
+        };
+
+        if is_exists_subquery(select_clause) {
+            return;
+        }
+        report.report(node, "list the columns explicitly instead of `SELECT *`");
+    }
+}
+
+/// SELECT 句の問い合わせが `EXISTS (subquery)` の subquery か
+/// 問い合わせから外側へは括弧とサブクエリのノードだけをたどり、`IF NOT EXISTS` などの EXISTS は見ない
+fn is_exists_subquery(select_clause: Node) -> bool {
+    let Some(mut node) = select_clause.parent() else {
+        return false;
+    };
+    while let Some(parent) = node.parent() {
+        if parent.kind().contains("exists") || follows_exists(parent, node) {
+            return true;
+        }
+        if !parent.kind().contains("subquery") && !parent.kind().contains("parenthesized") {
+            return false;
+        }
+        node = parent;
+    }
+    false
+}
+
+/// parent の子 node の直前 (開き括弧を除く) が EXISTS キーワードか
+fn follows_exists(parent: Node, node: Node) -> bool {
+    let mut cursor = parent.walk();
+    let mut previous = None;
+    for child in parent.children(&mut cursor) {
+        if child.id() == node.id() {
+            break;
+        }
+        if !child.is_extra() && !is_keyword(child, "(") {
+            previous = Some(child);
+        }
+    }
+    previous.is_some_and(|previous| is_keyword(previous, "EXISTS"))
+}
+
+/// AS を省略した別名
+struct ImplicitAlias;
+
+impl Rule for ImplicitAlias {
+    fn id(&self) -> &'static str {
+        "implicit-alias"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        if node.kind() != "alias" || has_keyword(node, "AS") {
+            return;
+        }
+        let Some(alias) = node
+            .child_by_field_name("alias")
+            .or_else(|| node.named_child(node.named_child_count().saturating_sub(1)))
+        else {
+            return;
+        };
+        report.report(
+            alias,
+            format!(
+                "use `AS {}` to make the alias explicit",
+                node_text(alias, src)
+            ),
+        );
+    }
+}
+
+/// `GROUP BY 1` のような列番号による指定
+struct PositionalGroupBy;
+
+impl Rule for PositionalGroupBy {
+    fn id(&self) -> &'static str {
+        "positional-group-by"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        let is_number = node.kind() == "number"
+            || (node.child_count() == 0
+                && node_text(node, src).chars().all(|c| c.is_ascii_digit()));
+        if !is_number || node_text(node, src).is_empty() {
+            return;
+        }
+        if node
+            .parent()
+            .is_some_and(|parent| parent.kind().starts_with("group_by"))
+        {
+            report.report(
+                node,
+                format!(
+                    "refer to the column by name instead of by position `{}`",
+                    node_text(node, src)
+                ),
+            );
+        }
+    }
+}
+
+/// カンマ区切りの結合と JOIN の混在
+struct MixedJoinStyle;
+
+impl Rule for MixedJoinStyle {
+    fn id(&self) -> &'static str {
+        "mixed-join-style"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, node: Node, _src: &str, report: &mut Reporter) {
+        if node.kind() != "from_clause" {
+            return;
+        }
+        let items = from_items(node);
+        if !items.joins.is_empty() && items.tables.len() > 1 {
+            report.report(
+                node,
+                "do not mix comma-separated tables with JOIN; use JOIN for every table",
+            );
+        }
+    }
+}
+
+/// 結合がある問い合わせでの修飾されていない列
+struct UnqualifiedColumn;
+
+impl Rule for UnqualifiedColumn {
+    fn id(&self) -> &'static str {
+        "unqualified-column"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, node: Node, src: &str, report: &mut Reporter) {
+        if node.kind() != "select_statement" {
+            return;
+        }
+        let Some(from_clause) = child_of_kind(node, "from_clause") else {
+            return;
+        };
+        let items = from_items(from_clause);
+        if items.tables.len() < 2 && items.joins.is_empty() {
+            return;
+        }
+
+        // SELECT 句で定義した別名は ORDER BY などで修飾なしに参照できる
+        let aliases = select_aliases(node, src);
+
+        let mut cursor = node.walk();
+        for clause in node.named_children(&mut cursor) {
+            match clause.kind() {
+                "with_clause" => {}
+                "from_clause" | "join_clause" => join_conditions(clause, src, &aliases, report),
+                _ => unqualified_identifiers(clause, src, &aliases, report),
+            }
+        }
+    }
+}
+
+fn select_aliases(statement: Node, src: &str) -> HashSet<String> {
+    let mut aliases = HashSet::new();
+    let Some(select_clause) = child_of_kind(statement, "select_clause") else {
+        return aliases;
+    };
+    collect_aliases(select_clause, src, &mut aliases);
+    aliases
+}
+
+fn collect_aliases(node: Node, src: &str, aliases: &mut HashSet<String>) {
+    if node.kind().ends_with("_statement") {
+        return;
+    }
+    if node.kind() == "alias"
+        && let Some(alias) = node
+            .child_by_field_name("alias")
+            .or_else(|| node.named_child(node.named_child_count().saturating_sub(1)))
+    {
+        aliases.insert(normalize_identifier(node_text(alias, src)));
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_aliases(child, src, aliases);
+    }
+}
+
+fn join_conditions(node: Node, src: &str, aliases: &HashSet<String>, report: &mut Reporter) {
+    let mut cursor = node.walk();
+    let mut after_on = false;
+    for child in node.children(&mut cursor) {
+        if is_keyword(child, "ON") {
+            after_on = true;
+        } else if child.kind() == "join_clause" {
+            join_conditions(child, src, aliases, report);
+        } else if after_on && child.is_named() {
+            unqualified_identifiers(child, src, aliases, report);
+        }
+    }
+}
+
+/// 入れ子の問い合わせ、別名の定義、関数名、型名を除いた修飾なしの名前を報告する
+fn unqualified_identifiers(
+    node: Node,
+    src: &str,
+    aliases: &HashSet<String>,
+    report: &mut Reporter,
+) {
+    match node.kind() {
+        kind if kind.ends_with("_statement") => {}
+        // `u.id` のような修飾された名前は、その中の識別子を見ない
+        _ if is_name(node) => {
+            let text = node_text(node, src);
+            if split_qualified_name(text).len() == 1
+                && !aliases.contains(&normalize_identifier(text))
+            {
+                report.report(
+                    node,
+                    format!(
+                        "qualify column `{}` with its table when the query has joins",
+                        node_text(node, src)
+                    ),
+                );
+            }
+        }
+        "alias" => {
+            if let Some(value) = node
+                .child_by_field_name("value")
+                .or_else(|| node.named_child(0))
+            {
+                unqualified_identifiers(value, src, aliases, report);
+            }
+        }
+        "function_call" => {
+            let function = node
+                .child_by_field_name("function")
+                .or_else(|| node.named_child(0).filter(|child| is_name(*child)));
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                if Some(child) != function {
+                    unqualified_identifiers(child, src, aliases, report);
+                }
+            }
+        }
+        kind if kind.ends_with("type") => {}
+        _ => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                unqualified_identifiers(child, src, aliases, report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::lint::{LintConfig, lint};
+    use crate::common::position::PositionEncoding;
+
+    fn findings(src: &str, rule_id: &str) -> usize {
+        lint(src, &LintConfig::default(), PositionEncoding::Utf8)
+            .iter()
+            .filter(|finding| finding.rule_id == rule_id)
+            .count()
+    }
+
+    #[test]
+    fn qualified_columns_in_joins() {
+        assert_eq!(
+            findings(
+                "SELECT u.id FROM u JOIN o ON u.id = o.uid;",
+                "unqualified-column"
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn unqualified_column_in_joins() {
+        assert_eq!(
+            findings(
+                "SELECT id FROM u JOIN o ON u.id = o.uid;",
+                "unqualified-column"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn mixed_join_style() {
+        assert_eq!(
+            findings(
+                "SELECT * FROM a, b JOIN c ON b.id = c.id;",
+                "mixed-join-style"
+            ),
+            1
+        );
+        assert_eq!(
+            findings(
+                "SELECT * FROM a JOIN b ON a.id = b.id JOIN c ON b.id = c.id;",
+                "mixed-join-style"
+            ),
+            0
+        );
+        assert_eq!(
+            findings("SELECT * FROM a, b WHERE a.id = b.id;", "mixed-join-style"),
+            0
+        );
+    }
+
+    #[test]
+    fn keyword_case_follows_first_keyword() {
+        assert_eq!(findings("SELECT a FROM t WHERE b = 1;", "keyword-case"), 0);
+        assert_eq!(findings("select a from t where b = 1;", "keyword-case"), 0);
+        assert_eq!(findings("SELECT a from t WHERE b = 1;", "keyword-case"), 1);
+        assert_eq!(findings("select a FROM t;", "keyword-case"), 1);
+    }
+
+    #[test]
+    fn select_star() {
+        assert_eq!(findings("SELECT * FROM t;", "select-star"), 1);
+        assert_eq!(findings("SELECT count(*) FROM t;", "select-star"), 0);
+        assert_eq!(findings("SELECT a * 2 FROM t;", "select-star"), 0);
+    }
+
+    #[test]
+    fn select_star_in_exists_subquery() {
+        assert_eq!(
+            findings(
+                "SELECT a FROM t WHERE EXISTS (SELECT * FROM s WHERE s.id = t.id);",
+                "select-star"
+            ),
+            0
+        );
+        assert_eq!(
+            findings(
+                "SELECT a FROM t WHERE NOT EXISTS (SELECT * FROM s WHERE s.id = t.id);",
+                "select-star"
+            ),
+            0
+        );
+        // EXISTS の中でも、さらに入れ子になった問い合わせは対象
+        assert_eq!(
+            findings(
+                "SELECT a FROM t WHERE EXISTS (SELECT 1 FROM s WHERE s.id IN (SELECT * FROM u));",
+                "select-star"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn select_star_after_if_not_exists() {
+        assert_eq!(
+            findings(
+                "CREATE TABLE IF NOT EXISTS t AS SELECT * FROM s;",
+                "select-star"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn implicit_alias() {
+        assert_eq!(findings("SELECT a x FROM t u;", "implicit-alias"), 2);
+        assert_eq!(findings("SELECT a AS x FROM t AS u;", "implicit-alias"), 0);
+    }
+
+    #[test]
+    fn positional_group_by() {
+        assert_eq!(
+            findings(
+                "SELECT a, count(*) FROM t GROUP BY 1;",
+                "positional-group-by"
+            ),
+            1
+        );
+        assert_eq!(
+            findings(
+                "SELECT a, count(*) FROM t GROUP BY a;",
+                "positional-group-by"
+            ),
+            0
+        );
+    }
+}
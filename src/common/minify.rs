@@ -0,0 +1,165 @@
+use tree_sitter::{Node, Tree};
+
+use super::format::same_structure_without_comments;
+use super::syntax::{is_keyword_token, node_text};
+use super::tree_sitter_sql::parse;
+
+/// 字句の端の文字の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    /// 英数字や引用符など、隣接すると 1 つの字句に繋がりうる文字
+    Word,
+    /// 隣接しても字句が変わらない括弧や区切り
+    Punctuation,
+    /// 隣接すると別の演算子やコメントになりうる記号
+    Operator,
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        '(' | ')' | '[' | ']' | ',' | ';' => CharClass::Punctuation,
+        // `1 .5` の `.` は数値の一部なので単語として扱う
+        c if c.is_alphanumeric() || matches!(c, '_' | '$' | '.' | '\'' | '"' | '`') => {
+            CharClass::Word
+        }
+        _ => CharClass::Operator,
+    }
+}
+
+/// コメントを除き、字句の間の空白を字句が繋がらない最小限にした SQL
+/// 文字列リテラルと引用符付き識別子は書かれたまま残す
+/// 結果をパースし直して元と同じ構造にならない場合は None を返す
+pub fn minify(tree: &Tree, src: &str) -> Option<String> {
+    let minified = join_tokens(tree, src);
+    let reparsed = parse(&minified);
+    same_structure_without_comments(tree.root_node(), src, reparsed.root_node(), &minified)
+        .then_some(minified)
+}
+
+fn join_tokens(tree: &Tree, src: &str) -> String {
+    let leaves = leaf_tokens(tree.root_node(), src);
+
+    let mut out = String::new();
+    let mut previous: Option<Node> = None;
+    for leaf in leaves {
+        let text = node_text(leaf, src);
+        // 複数語のキーワード (`ORDER BY` など) の中の空白もまとめる
+        let text = if is_keyword_token(leaf, src) {
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            text.to_string()
+        };
+
+        if let Some(previous) = previous {
+            out.push_str(separator(previous, leaf, src));
+        }
+        out.push_str(&text);
+        previous = Some(leaf);
+    }
+
+    out
+}
+
+/// node の中の字句のノード
+/// コメントと長さ 0 の MISSING ノードは除き、文字列などのリテラルと、子ノードが覆っていないテキストを持つノードは 1 つの字句として扱う
+pub fn leaf_tokens<'a>(node: Node<'a>, src: &str) -> Vec<Node<'a>> {
+    let mut leaves = Vec::new();
+    collect_leaves(node, src, &mut leaves);
+    leaves
+}
+
+fn collect_leaves<'a>(node: Node<'a>, src: &str, leaves: &mut Vec<Node<'a>>) {
+    if node.kind().contains("comment") {
+        return;
+    }
+    if node.child_count() == 0 || node.kind().contains("string") || has_uncovered_text(node, src) {
+        if node.start_byte() < node.end_byte() {
+            leaves.push(node);
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_leaves(child, src, leaves);
+    }
+}
+
+/// 子ノードの間に空白以外のテキストがあるか
+fn has_uncovered_text(node: Node, src: &str) -> bool {
+    let mut offset = node.start_byte();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if !src[offset..child.start_byte().max(offset)]
+            .trim()
+            .is_empty()
+        {
+            return true;
+        }
+        offset = offset.max(child.end_byte());
+    }
+    !src[offset..node.end_byte().max(offset)].trim().is_empty()
+}
+
+/// 2 つの字句の間に必要な区切り
+fn separator(previous: Node, next: Node, src: &str) -> &'static str {
+    let between = &src[previous.end_byte()..next.start_byte().max(previous.end_byte())];
+    if between.is_empty() {
+        return "";
+    }
+
+    // 改行を挟んで並んだ文字列リテラルは連結される
+    let previous_text = node_text(previous, src);
+    let next_text = node_text(next, src);
+    if previous_text.ends_with('\'') && next_text.starts_with('\'') && between.contains('\n') {
+        return "\n";
+    }
+
+    let (Some(last), Some(first)) = (previous_text.chars().last(), next_text.chars().next()) else {
+        return " ";
+    };
+    match (char_class(last), char_class(first)) {
+        (CharClass::Punctuation, _) | (_, CharClass::Punctuation) => "",
+        (CharClass::Word, CharClass::Operator) | (CharClass::Operator, CharClass::Word) => "",
+        _ => " ",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minified(src: &str) -> String {
+        minify(&parse(src), src).unwrap()
+    }
+
+    #[test]
+    fn keeps_literals_and_quoted_identifiers() {
+        assert_eq!(
+            minified("SELECT  'a  b' ,  \"My  Col\"\n  FROM  t ;"),
+            "SELECT 'a  b',\"My  Col\" FROM t;"
+        );
+    }
+
+    #[test]
+    fn removes_comments() {
+        assert_eq!(
+            minified("SELECT a, -- first\n  /* second */ b\nFROM t; -- end\n"),
+            "SELECT a,b FROM t;"
+        );
+    }
+
+    #[test]
+    fn keeps_comment_markers_inside_strings() {
+        assert_eq!(
+            minified("SELECT '--x', '/* y */' -- z\nFROM t;"),
+            "SELECT '--x','/* y */' FROM t;"
+        );
+    }
+
+    #[test]
+    fn keeps_operators_apart() {
+        // `- -1` を `--1` にするとコメントになる
+        assert_eq!(minified("SELECT 1 - -1;"), "SELECT 1- -1;");
+    }
+}
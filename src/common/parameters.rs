@@ -0,0 +1,579 @@
+use std::ops::Range;
+
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::columns::{ColumnReference, extract_columns};
+use super::position::{PositionEncoding, SourceRange};
+use super::statements::{statement_spans, tokens};
+use super::syntax::node_text;
+
+/// 比較の演算子とみなす字句
+const COMPARISON_OPERATORS: &[&str] = &[
+    "=", "<>", "!=", "<", ">", "<=", ">=", "LIKE", "ILIKE", "IN", "BETWEEN", "IS",
+];
+
+/// 直後に式が始まるキーワード
+/// 列名などの直後の `?` は PostgreSQL の演算子 (`jsonb ? 'key'`) なので、バインド変数とみなさない
+const EXPRESSION_KEYWORDS: &[&str] = &[
+    "SELECT",
+    "WHERE",
+    "AND",
+    "OR",
+    "NOT",
+    "ON",
+    "HAVING",
+    "WHEN",
+    "THEN",
+    "ELSE",
+    "CASE",
+    "IN",
+    "LIKE",
+    "ILIKE",
+    "IS",
+    "BETWEEN",
+    "FROM",
+    "ANY",
+    "ALL",
+    "SOME",
+    "LIMIT",
+    "OFFSET",
+    "FETCH",
+    "FIRST",
+    "NEXT",
+    "VALUES",
+    "RETURNING",
+    "ESCAPE",
+];
+
+/// バインド変数の書き方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterStyle {
+    /// `?` (JDBC、ODBC)
+    QuestionMark,
+    /// `$1` (PostgreSQL)
+    Dollar,
+    /// `:name` (Oracle、SQLAlchemy)
+    Colon,
+    /// `@name` (SQL Server)
+    AtSign,
+    /// `%(name)s` (Python の pyformat)
+    Pyformat,
+}
+
+/// バインド変数と比較される式
+#[derive(Debug, Clone, Serialize)]
+pub struct ParameterContext {
+    /// 比較される列や式 (例: `users.id`)
+    pub expression: Option<String>,
+    /// 比較の演算子やキーワード (例: `=`、`IN`、`LIMIT`)
+    pub operator: Option<String>,
+    /// expression が列の場合、その列のテーブル
+    pub table: Option<String>,
+    /// バインド変数を含む式全体 (例: `users.id = $1`)
+    pub text: String,
+}
+
+/// バインド変数 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct Parameter {
+    /// スクリプト内での出現順 (0 始まり)
+    pub index: usize,
+    /// バインド変数を含む文の番号
+    pub statement: usize,
+    pub style: ParameterStyle,
+    /// 名前 (`:name` の name) または番号 (`$1` の 1)。`?` の場合は None
+    pub name: Option<String>,
+    pub text: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+    pub context: Option<ParameterContext>,
+}
+
+/// スクリプトのバインド変数を、比較される列や式とともに集める
+/// 文字列・コメントの中と `::` による型変換は除く
+pub fn find_parameters(src: &str, encoding: PositionEncoding) -> Vec<Parameter> {
+    let mut parameters = Vec::new();
+
+    for (statement, span) in statement_spans(src).into_iter().enumerate() {
+        let tokens = tokens(src, span.start, span.end);
+        let tree = span.parse(src);
+        let columns = extract_columns(&tree, src, encoding);
+
+        let mut i = 0;
+        while i < tokens.len() {
+            let Some((style, name, count)) = detect(src, &tokens, i) else {
+                i += 1;
+                continue;
+            };
+            let range = tokens[i].start..tokens[i + count - 1].end;
+            let context = tree_context(&tree, src, range.clone(), &columns)
+                .or_else(|| lexical_context(src, &tokens, i, i + count));
+
+            parameters.push(Parameter {
+                index: parameters.len(),
+                statement,
+                style,
+                name,
+                text: src[range.clone()].to_string(),
+                range: SourceRange::from_bytes(src, range.start, range.end, encoding),
+                context,
+            });
+            i += count;
+        }
+    }
+
+    parameters
+}
+
+/// tokens[i] から始まるバインド変数の書き方、名前、字句の数
+fn detect(
+    src: &str,
+    tokens: &[Range<usize>],
+    i: usize,
+) -> Option<(ParameterStyle, Option<String>, usize)> {
+    let text = |i: usize| tokens.get(i).map_or("", |token| &src[token.clone()]);
+    // 間に空白を挟まずに続く字句
+    let adjacent = |i: usize| {
+        tokens
+            .get(i + 1)
+            .is_some_and(|next| tokens[i].end == next.start)
+    };
+    let is_word = |i: usize| {
+        text(i)
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+    };
+    // 直前の字句が列名やリテラル、閉じ括弧で終わり、tokens[i] が二項演算子の位置にある
+    let after_operand = i > 0 && {
+        let previous = text(i - 1);
+        matches!(previous, ")" | "]")
+            || previous.starts_with(['\'', '"'])
+            || (previous
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                && !EXPRESSION_KEYWORDS
+                    .iter()
+                    .any(|keyword| previous.eq_ignore_ascii_case(keyword)))
+    };
+
+    match text(i) {
+        // `jsonb ? 'key'`、`?|`、`?&` は PostgreSQL の演算子
+        "?" if after_operand || (adjacent(i) && matches!(text(i + 1), "|" | "&")) => None,
+        "?" => Some((ParameterStyle::QuestionMark, None, 1)),
+        "$" if adjacent(i) && text(i + 1).chars().all(|c| c.is_ascii_digit()) => {
+            Some((ParameterStyle::Dollar, Some(text(i + 1).to_string()), 2))
+        }
+        // `::` による型変換と `a[1:2]` を除く
+        ":" if adjacent(i)
+            && is_word(i + 1)
+            && !(i > 0 && adjacent(i - 1) && text(i - 1) == ":") =>
+        {
+            Some((ParameterStyle::Colon, Some(text(i + 1).to_string()), 2))
+        }
+        // `@>`、`<@`、`@@` と絶対値の `@ x` は PostgreSQL の演算子
+        "@" if adjacent(i)
+            && is_word(i + 1)
+            && !after_operand
+            && !(i > 0 && adjacent(i - 1) && matches!(text(i - 1), "<" | "@")) =>
+        {
+            Some((ParameterStyle::AtSign, Some(text(i + 1).to_string()), 2))
+        }
+        "%" if (i..i + 4).all(adjacent)
+            && text(i + 1) == "("
+            && is_word(i + 2)
+            && text(i + 3) == ")"
+            && text(i + 4) == "s" =>
+        {
+            Some((ParameterStyle::Pyformat, Some(text(i + 2).to_string()), 5))
+        }
+        _ => None,
+    }
+}
+
+/// ツリーの二項演算から、バインド変数と比較される式を求める
+fn tree_context(
+    tree: &Tree,
+    src: &str,
+    range: Range<usize>,
+    columns: &[ColumnReference],
+) -> Option<ParameterContext> {
+    let node = tree
+        .root_node()
+        .descendant_for_byte_range(range.start, range.end)?;
+
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        if parent.kind().ends_with("_statement") || parent.kind().ends_with("_clause") {
+            return None;
+        }
+        if parent.kind() == "binary_expression" && !parent.has_error() {
+            let mut cursor = parent.walk();
+            let other = parent
+                .named_children(&mut cursor)
+                .find(|child| child.end_byte() <= range.start || child.start_byte() >= range.end)?;
+            let other = without_type_cast(other);
+            let mut cursor = parent.walk();
+            let operator = parent
+                .child_by_field_name("operator")
+                .or_else(|| parent.children(&mut cursor).find(|child| !child.is_named()))
+                .map(|operator| node_text(operator, src).to_uppercase());
+
+            return Some(ParameterContext {
+                expression: Some(node_text(other, src).to_string()),
+                operator,
+                table: table_of(other, columns),
+                text: collapse(node_text(parent, src)),
+            });
+        }
+        current = parent;
+    }
+    None
+}
+
+/// `b::int` のような型変換の場合、変換される式
+fn without_type_cast(node: Node) -> Node {
+    let mut node = node;
+    while node.kind() == "type_cast"
+        && let Some(value) = node.named_child(0)
+    {
+        node = value;
+    }
+    node
+}
+
+/// 式が列参照の場合、その列のテーブル
+fn table_of(node: Node, columns: &[ColumnReference]) -> Option<String> {
+    columns
+        .iter()
+        .find(|column| {
+            column.range.start_byte == node.start_byte() && column.range.end_byte == node.end_byte()
+        })
+        .and_then(|column| column.table.as_ref())
+        .map(|table| table.name.clone())
+}
+
+/// 構文エラーでツリーから求められない場合に、前後の字句から比較される式を求める
+fn lexical_context(
+    src: &str,
+    tokens: &[Range<usize>],
+    first: usize,
+    end: usize,
+) -> Option<ParameterContext> {
+    let text = |i: usize| &src[tokens[i].clone()];
+    let parameter = &src[tokens[first].start..tokens[end - 1].end];
+
+    // 直前の比較演算子 (`IN (` の場合は括弧とそれまでの要素を読み飛ばす)
+    let mut before = first;
+    if let Some(open) = unclosed_paren(src, tokens, first) {
+        let keyword = open.checked_sub(1).map(text);
+        if keyword.is_some_and(|keyword| keyword.eq_ignore_ascii_case("IN")) {
+            before = open;
+        } else if keyword.is_some_and(|keyword| keyword.eq_ignore_ascii_case("VALUES")) {
+            return insert_context(src, tokens, open, first, parameter);
+        }
+    }
+    if let Some((operator, operator_start)) = operator_before(src, tokens, before)
+        && let Some((expression, expression_start)) = name_before(src, tokens, operator_start)
+    {
+        return Some(ParameterContext {
+            expression: Some(expression),
+            operator: Some(operator),
+            table: None,
+            text: collapse(&src[tokens[expression_start].start..tokens[end - 1].end]),
+        });
+    }
+
+    // `$1 = users.id` のように右辺が列の場合
+    if let Some((operator, operator_end)) = operator_after(src, tokens, end)
+        && let Some((expression, expression_end)) = name_after(src, tokens, operator_end)
+    {
+        return Some(ParameterContext {
+            expression: Some(expression),
+            operator: Some(operator),
+            table: None,
+            text: collapse(&src[tokens[first].start..tokens[expression_end - 1].end]),
+        });
+    }
+
+    // LIMIT ? や OFFSET ?
+    let keyword = first.checked_sub(1).map(text)?;
+    if keyword.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Some(ParameterContext {
+            expression: None,
+            operator: Some(keyword.to_uppercase()),
+            table: None,
+            text: collapse(&src[tokens[first - 1].start..tokens[end - 1].end]),
+        });
+    }
+    None
+}
+
+/// `INSERT INTO t (a, b) VALUES (?, ?)` の、バインド変数と同じ位置の列
+fn insert_context(
+    src: &str,
+    tokens: &[Range<usize>],
+    open: usize,
+    first: usize,
+    parameter: &str,
+) -> Option<ParameterContext> {
+    let text = |i: usize| &src[tokens[i].clone()];
+    let position = (open + 1..first)
+        .filter(|&i| text(i) == "," && unclosed_paren(src, tokens, i) == Some(open))
+        .count();
+
+    // VALUES より前の最初の括弧が列の一覧
+    let list_open = (0..open).find(|&i| text(i) == "(")?;
+    let mut column = None;
+    let mut index = 0;
+    for i in list_open + 1..open {
+        match text(i) {
+            ")" => break,
+            "," => index += 1,
+            token if index == position && column.is_none() => column = Some(token.to_string()),
+            _ => {}
+        }
+    }
+    let column = column?;
+
+    Some(ParameterContext {
+        text: format!("{} = {}", column, parameter),
+        expression: Some(column),
+        operator: Some("VALUES".to_string()),
+        table: None,
+    })
+}
+
+/// tokens[i] を囲む、閉じていない `(` の位置
+fn unclosed_paren(src: &str, tokens: &[Range<usize>], i: usize) -> Option<usize> {
+    let mut depth = 0;
+    for j in (0..i).rev() {
+        match &src[tokens[j].clone()] {
+            ")" => depth += 1,
+            "(" if depth == 0 => return Some(j),
+            "(" => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// tokens[end] の直前の比較演算子と、その最初の字句の位置
+/// `<=` のように複数の字句に分かれた演算子はまとめる
+fn operator_before(src: &str, tokens: &[Range<usize>], end: usize) -> Option<(String, usize)> {
+    let mut start = end.checked_sub(1)?;
+    while start > 0
+        && tokens[start - 1].end == tokens[start].start
+        && is_operator_char(&src[tokens[start - 1].clone()])
+        && is_operator_char(&src[tokens[start].clone()])
+    {
+        start -= 1;
+    }
+    let operator = src[tokens[start].start..tokens[end - 1].end].to_uppercase();
+    // `NOT IN`、`NOT LIKE`
+    let operator = if start > 0 && src[tokens[start - 1].clone()].eq_ignore_ascii_case("NOT") {
+        start -= 1;
+        format!("NOT {}", operator)
+    } else {
+        operator
+    };
+
+    let base = operator.trim_start_matches("NOT ");
+    COMPARISON_OPERATORS
+        .contains(&base)
+        .then_some((operator, start))
+}
+
+fn operator_after(src: &str, tokens: &[Range<usize>], start: usize) -> Option<(String, usize)> {
+    let mut end = start;
+    while end < tokens.len()
+        && is_operator_char(&src[tokens[end].clone()])
+        && (end == start || tokens[end - 1].end == tokens[end].start)
+    {
+        end += 1;
+    }
+    if end == start {
+        return None;
+    }
+    let operator = src[tokens[start].start..tokens[end - 1].end].to_string();
+    COMPARISON_OPERATORS
+        .contains(&operator.as_str())
+        .then_some((operator, end))
+}
+
+fn is_operator_char(text: &str) -> bool {
+    matches!(text, "=" | "<" | ">" | "!")
+}
+
+/// tokens[end] の直前の修飾付きの名前 (`users.id`) と、その最初の字句の位置
+/// `b::int` のような型変換は読み飛ばし、変換される名前を返す
+fn name_before(src: &str, tokens: &[Range<usize>], end: usize) -> Option<(String, usize)> {
+    let text = |i: usize| &src[tokens[i].clone()];
+    let mut end = end;
+    while end >= 4 && is_name(text(end - 1)) && text(end - 2) == ":" && text(end - 3) == ":" {
+        end -= 3;
+    }
+
+    let mut start = end.checked_sub(1)?;
+    if !is_name(&src[tokens[start].clone()]) {
+        return None;
+    }
+    while start >= 2
+        && &src[tokens[start - 1].clone()] == "."
+        && is_name(&src[tokens[start - 2].clone()])
+    {
+        start -= 2;
+    }
+    Some((
+        src[tokens[start].start..tokens[end - 1].end].to_string(),
+        start,
+    ))
+}
+
+fn name_after(src: &str, tokens: &[Range<usize>], start: usize) -> Option<(String, usize)> {
+    if !tokens
+        .get(start)
+        .is_some_and(|token| is_name(&src[token.clone()]))
+    {
+        return None;
+    }
+    let mut end = start + 1;
+    while end + 1 < tokens.len()
+        && &src[tokens[end].clone()] == "."
+        && is_name(&src[tokens[end + 1].clone()])
+    {
+        end += 2;
+    }
+    Some((
+        src[tokens[start].start..tokens[end - 1].end].to_string(),
+        end,
+    ))
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with('"')
+        || text
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+/// 空白を 1 つにまとめる
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(src: &str) -> ParameterContext {
+        let parameters = find_parameters(src, PositionEncoding::Utf8);
+        assert_eq!(parameters.len(), 1, "{}", src);
+        parameters[0].context.clone().unwrap()
+    }
+
+    /// ツリーを使わずに、字句だけから求めた最初のバインド変数の文脈
+    fn lexical(src: &str) -> Option<ParameterContext> {
+        let tokens = tokens(src, 0, src.len());
+        let (first, (_, _, count)) = (0..tokens.len())
+            .find_map(|i| Some((i, detect(src, &tokens, i)?)))
+            .unwrap();
+        lexical_context(src, &tokens, first, first + count)
+    }
+
+    #[test]
+    fn tree_context_of_comparison() {
+        let context = context("SELECT * FROM users WHERE users.id = $1;");
+        assert_eq!(context.expression.as_deref(), Some("users.id"));
+        assert_eq!(context.operator.as_deref(), Some("="));
+        assert_eq!(context.table.as_deref(), Some("users"));
+    }
+
+    #[test]
+    fn tree_context_skips_type_cast() {
+        let context = context("SELECT * FROM t WHERE b::int = $1;");
+        assert_eq!(context.expression.as_deref(), Some("b"));
+        assert_eq!(context.operator.as_deref(), Some("="));
+    }
+
+    #[test]
+    fn lexical_context_of_comparison() {
+        let context = lexical("SELECT * FROM users WHERE users.id >= :id AND").unwrap();
+        assert_eq!(context.expression.as_deref(), Some("users.id"));
+        assert_eq!(context.operator.as_deref(), Some(">="));
+        assert_eq!(context.text, "users.id >= :id");
+    }
+
+    #[test]
+    fn lexical_context_skips_type_cast() {
+        let context = lexical("SELECT * FROM t WHERE b::int = $1 AND").unwrap();
+        assert_eq!(context.expression.as_deref(), Some("b"));
+        assert_eq!(context.operator.as_deref(), Some("="));
+        assert_eq!(context.text, "b::int = $1");
+    }
+
+    #[test]
+    fn lexical_context_of_reversed_comparison() {
+        let context = lexical("WHERE ? = t.a").unwrap();
+        assert_eq!(context.expression.as_deref(), Some("t.a"));
+        assert_eq!(context.text, "? = t.a");
+    }
+
+    /// 字句だけから見つけたバインド変数のテキスト
+    fn detected(src: &str) -> Vec<&str> {
+        let tokens = tokens(src, 0, src.len());
+        let mut found = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match detect(src, &tokens, i) {
+                Some((_, _, count)) => {
+                    found.push(&src[tokens[i].start..tokens[i + count - 1].end]);
+                    i += count;
+                }
+                None => i += 1,
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn question_marks_in_expression_position() {
+        assert_eq!(
+            detected("SELECT ? FROM t WHERE a = ? AND b IN (?, ?) LIMIT ?"),
+            vec!["?"; 5]
+        );
+    }
+
+    #[test]
+    fn jsonb_question_mark_operators() {
+        assert_eq!(
+            detected("SELECT * FROM t WHERE data ? 'key' AND id = ?"),
+            vec!["?"]
+        );
+        assert!(detected("WHERE data ?| array['a', 'b'] AND data ?& array['c']").is_empty());
+        assert!(detected("WHERE data->'tags' ? 'x' OR (data) ? 'y' OR col?'z'").is_empty());
+    }
+
+    #[test]
+    fn at_sign_operators() {
+        assert_eq!(
+            detected("WHERE tags @> :tags AND ? <@ tags AND ids <@ids AND doc @@to_tsquery(:q)"),
+            vec![":tags", "?", ":q"]
+        );
+        assert_eq!(
+            detected("SELECT @ -5, @x FROM t WHERE id = @id"),
+            vec!["@x", "@id"]
+        );
+    }
+
+    #[test]
+    fn type_cast_is_not_a_parameter() {
+        let src = "SELECT a::int FROM t";
+        let tokens = tokens(src, 0, src.len());
+        assert!((0..tokens.len()).all(|i| detect(src, &tokens, i).is_none()));
+    }
+}
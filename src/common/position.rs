@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Point};
+
+/// 1 始まりの行と列 (列は PositionEncoding の単位)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// バイト位置の行と列。列は encoding の単位で数える
+    pub fn at(src: &str, byte: usize, encoding: PositionEncoding) -> Self {
+        Self::from_point(src, byte, point_at(src, byte), encoding)
+    }
+
+    /// tree-sitter の Point (byte の位置のもの) を、列を encoding の単位で数え直して変換する
+    fn from_point(src: &str, byte: usize, point: Point, encoding: PositionEncoding) -> Self {
+        Self {
+            line: point.row + 1,
+            column: encoding.column(src, byte, point.column) + 1,
+        }
+    }
+}
+
+/// ソース上の範囲
+/// 結果の構造体に `#[serde(flatten)]` で埋め込んで使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SourceRange {
+    pub start: Position,
+    pub end: Position,
+    pub start_byte: usize,
+    pub end_byte: usize,
+}
+
+impl SourceRange {
+    /// ノードのソース上の範囲
+    pub fn new(node: Node, src: &str, encoding: PositionEncoding) -> Self {
+        Self {
+            start: Position::from_point(src, node.start_byte(), node.start_position(), encoding),
+            end: Position::from_point(src, node.end_byte(), node.end_position(), encoding),
+            start_byte: node.start_byte(),
+            end_byte: node.end_byte(),
+        }
+    }
+
+    /// バイト範囲からソース上の範囲を作る
+    pub fn from_bytes(
+        src: &str,
+        start_byte: usize,
+        end_byte: usize,
+        encoding: PositionEncoding,
+    ) -> Self {
+        Self {
+            start: Position::at(src, start_byte, encoding),
+            end: Position::at(src, end_byte, encoding),
+            start_byte,
+            end_byte,
+        }
+    }
+}
+
+/// バイト位置を tree-sitter の Point (0 始まりの行と、行内のバイト位置) にする
+pub fn point_at(src: &str, byte: usize) -> Point {
+    let before = &src.as_bytes()[..byte];
+    let row = before.iter().filter(|b| **b == b'\n').count();
+    let line_start = before
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+
+    Point::new(row, byte - line_start)
+}
+
+/// 1 始まりの行番号の範囲 (end_line を含む) をバイト範囲にする
+/// 範囲がソースの外にはみ出す部分は切り詰める
+pub fn line_range(src: &str, start_line: usize, end_line: usize) -> Range<usize> {
+    let line_start = |line: usize| {
+        if line <= 1 {
+            return 0;
+        }
+        src.match_indices('\n')
+            .nth(line - 2)
+            .map_or(src.len(), |(i, _)| i + 1)
+    };
+    let start = line_start(start_line);
+    let end = line_start(end_line.saturating_add(1)).max(start);
+    start..end
+}
+
+/// 結果の列番号を数える単位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum PositionEncoding {
+    /// UTF-8 のバイト数
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-16 のコードユニット数 (LSP の既定)
+    #[serde(rename = "utf-16")]
+    Utf16,
+    /// Unicode のコードポイント数
+    #[serde(rename = "utf-32")]
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn count(self, text: &str) -> usize {
+        match self {
+            Self::Utf8 => text.len(),
+            Self::Utf16 => text.encode_utf16().count(),
+            Self::Utf32 => text.chars().count(),
+        }
+    }
+
+    /// 行内のバイト単位の列 (0 始まり) を、このエンコーディングの列にする
+    /// byte はその位置のソース全体でのバイト位置
+    pub fn column(self, src: &str, byte: usize, byte_column: usize) -> usize {
+        match src.get(byte.saturating_sub(byte_column)..byte) {
+            Some(line) => self.count(line),
+            None => byte_column,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_range_covers_whole_lines() {
+        let src = "a\nbb\nccc";
+        assert_eq!(line_range(src, 1, 1), 0..2);
+        assert_eq!(line_range(src, 2, 3), 2..src.len());
+        assert_eq!(line_range(src, 3, 1), 5..5);
+    }
+
+    #[test]
+    fn line_range_clamps_lines_past_the_end() {
+        let src = "a\nbb\nccc";
+        assert_eq!(line_range(src, 2, 10), 2..src.len());
+        assert_eq!(line_range(src, 10, 20), src.len()..src.len());
+        assert_eq!(line_range(src, 1, usize::MAX), 0..src.len());
+        assert_eq!(
+            line_range(src, usize::MAX, usize::MAX),
+            src.len()..src.len()
+        );
+    }
+
+    #[test]
+    fn column_counts_in_encoding() {
+        let src = "a\n😀é = 1";
+        let byte = src.find('=').unwrap();
+        let byte_column = point_at(src, byte).column;
+        assert_eq!(byte_column, 7);
+        assert_eq!(PositionEncoding::Utf8.column(src, byte, byte_column), 7);
+        assert_eq!(PositionEncoding::Utf16.column(src, byte, byte_column), 4);
+        assert_eq!(PositionEncoding::Utf32.column(src, byte, byte_column), 3);
+    }
+
+    #[test]
+    fn range_counts_columns_in_encoding() {
+        let src = "a\n😀é = 1";
+        let byte = src.find('=').unwrap();
+        let range = |encoding| SourceRange::from_bytes(src, byte, byte + 1, encoding);
+        assert_eq!(
+            range(PositionEncoding::Utf8).start,
+            Position { line: 2, column: 8 }
+        );
+        assert_eq!(range(PositionEncoding::Utf16).start.column, 5);
+        assert_eq!(range(PositionEncoding::Utf32).end.column, 5);
+        assert_eq!(range(PositionEncoding::Utf16).start_byte, byte);
+    }
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+use tree_sitter::{Node, Query, QueryCursor, QueryError, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+
+/// クエリのキャプチャ 1 件
+#[derive(Debug, Serialize)]
+pub struct CaptureResult {
+    pub name: String,
+    pub kind: &'static str,
+    pub text: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// クエリのマッチ 1 件
+#[derive(Debug, Serialize)]
+pub struct MatchResult {
+    /// マッチしたパターンの、クエリ内での番号 (0 始まり)
+    pub pattern_index: usize,
+    pub captures: Vec<CaptureResult>,
+}
+
+/// tree-sitter のクエリをツリーに対して実行し、すべてのマッチを返す
+pub fn execute_query(
+    tree: &Tree,
+    src: &str,
+    pattern: &str,
+    encoding: PositionEncoding,
+) -> Result<Vec<MatchResult>, QueryError> {
+    let query = Query::new(tree.language(), pattern)?;
+    let capture_names = query.capture_names();
+
+    let mut cursor = QueryCursor::new();
+    let matches = cursor
+        .matches(&query, tree.root_node(), src.as_bytes())
+        .map(|m| MatchResult {
+            pattern_index: m.pattern_index,
+            captures: m
+                .captures
+                .iter()
+                .map(|capture| {
+                    to_capture_result(
+                        &capture_names[capture.index as usize],
+                        capture.node,
+                        src,
+                        encoding,
+                    )
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(matches)
+}
+
+fn to_capture_result(
+    name: &str,
+    node: Node,
+    src: &str,
+    encoding: PositionEncoding,
+) -> CaptureResult {
+    CaptureResult {
+        name: name.to_string(),
+        kind: node.kind(),
+        text: src[node.byte_range()].to_string(),
+        range: SourceRange::new(node, src, encoding),
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+
+use super::tree_output::OutputFormat;
+
+/// ドキュメントごとに公開するリソースの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// パース結果のツリー
+    Tree,
+    /// 構文エラーの一覧
+    Diagnostics,
+    /// 参照しているテーブルの一覧
+    Tables,
+}
+
+impl ResourceKind {
+    pub const ALL: [Self; 3] = [Self::Tree, Self::Diagnostics, Self::Tables];
+
+    /// URI の最後の部分
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tree => "tree",
+            Self::Diagnostics => "diagnostics",
+            Self::Tables => "tables",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Tree => "syntax tree of the document, in the server's output format",
+            Self::Diagnostics => "syntax errors of the document with their locations",
+            Self::Tables => "tables the document reads, writes, creates or drops",
+        }
+    }
+
+    /// ツリーは設定の出力形式によってテキストか JSON になる
+    pub fn mime_type(self, format: OutputFormat) -> &'static str {
+        match (self, format) {
+            (Self::Tree, OutputFormat::Text | OutputFormat::Sexp) => "text/plain",
+            _ => "application/json",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+const PREFIX: &str = "sql://doc/";
+
+/// ID に使えない文字はすべて `%XX` にする
+const ID_UNRESERVED: &[u8] = b"-._~";
+
+/// パスの区切りの `/` はそのまま残す
+const PATH_UNRESERVED: &[u8] = b"/-._~";
+
+/// `sql://doc/{id}/{kind}`
+pub fn document_uri(document_id: &str, kind: ResourceKind) -> String {
+    format!(
+        "{}{}/{}",
+        PREFIX,
+        percent_encode(document_id, ID_UNRESERVED),
+        kind.name()
+    )
+}
+
+/// resources/templates/list に載せる `sql://doc/{id}/{kind}` のテンプレート
+pub fn document_uri_template(kind: ResourceKind) -> String {
+    format!("{}{{id}}/{}", PREFIX, kind.name())
+}
+
+/// document_uri の逆。形式が違う場合は None
+pub fn parse_document_uri(uri: &str) -> Option<(String, ResourceKind)> {
+    let (document_id, name) = uri.strip_prefix(PREFIX)?.split_once('/')?;
+    let document_id = percent_decode(document_id)?;
+    if document_id.is_empty() {
+        return None;
+    }
+    Some((document_id, ResourceKind::from_name(name)?))
+}
+
+const DIAGNOSTICS_PREFIX: &str = "sql://diagnostics/";
+
+/// 監視しているファイルの構文エラーのリソース `sql://diagnostics/{path}`
+/// path は先頭の `/` を除いた絶対パス
+pub fn file_diagnostics_uri(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    format!(
+        "{}{}",
+        DIAGNOSTICS_PREFIX,
+        percent_encode(path.trim_start_matches('/'), PATH_UNRESERVED)
+    )
+}
+
+/// file_diagnostics_uri の逆。形式が違う場合は None
+pub fn parse_file_diagnostics_uri(uri: &str) -> Option<PathBuf> {
+    let path = percent_decode(uri.strip_prefix(DIAGNOSTICS_PREFIX)?)?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(Path::new("/").join(path))
+}
+
+/// 英数字と unreserved 以外の文字を `%XX` にする
+fn percent_encode(text: &str, unreserved: &[u8]) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || unreserved.contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// `%XX` を元の文字に戻す。結果が UTF-8 でない場合は None
+pub fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_uri_round_trip() {
+        for id in ["1", "a b", "a/b", "100%", "?#[]@!$&'()*+,;=", "文書"] {
+            for kind in ResourceKind::ALL {
+                let uri = document_uri(id, kind);
+                assert_eq!(
+                    uri.matches('/').count(),
+                    4,
+                    "the id must not add path segments: {}",
+                    uri
+                );
+                assert_eq!(parse_document_uri(&uri), Some((id.to_string(), kind)));
+            }
+        }
+        assert_eq!(
+            document_uri("a/b", ResourceKind::Tree),
+            "sql://doc/a%2Fb/tree"
+        );
+    }
+
+    #[test]
+    fn document_uri_rejects_unknown_forms() {
+        assert_eq!(parse_document_uri("sql://doc/1/ast"), None);
+        assert_eq!(parse_document_uri("sql://doc/1/tree/extra"), None);
+        assert_eq!(parse_document_uri("sql://doc/1"), None);
+        assert_eq!(parse_document_uri("sql://doc//tree"), None);
+        assert_eq!(parse_document_uri("sql://doc/%FF/tree"), None);
+        assert_eq!(parse_document_uri("file:///1/tree"), None);
+        assert_eq!(
+            document_uri_template(ResourceKind::Diagnostics),
+            "sql://doc/{id}/diagnostics"
+        );
+    }
+
+    #[test]
+    fn file_diagnostics_uri_round_trip() {
+        for path in [
+            "/home/user/q.sql",
+            "/home/my queries/100% done.sql",
+            "/srv/a?b#c[d]@e.sql",
+            "/データ/検索.sql",
+        ] {
+            let uri = file_diagnostics_uri(Path::new(path));
+            assert!(!uri.contains([' ', '?', '#', '[', ']', '@']), "{}", uri);
+            assert_eq!(parse_file_diagnostics_uri(&uri), Some(PathBuf::from(path)));
+        }
+        assert_eq!(
+            file_diagnostics_uri(Path::new("/a b/c.sql")),
+            "sql://diagnostics/a%20b/c.sql"
+        );
+        assert_eq!(parse_file_diagnostics_uri("sql://diagnostics/"), None);
+        assert_eq!(parse_file_diagnostics_uri("sql://doc/1/tree"), None);
+        assert_eq!(
+            parse_file_diagnostics_uri("sql://diagnostics/%FF.sql"),
+            None
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::ops::Range;
+
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::diagnostics::{Diagnostic, collect_diagnostics};
+use super::position::{PositionEncoding, SourceRange};
+use super::tree_sitter_sql::parse_range;
+
+/// スクリプト中のトップレベルの文 1 つ
+#[derive(Debug, Clone, Serialize)]
+pub struct Statement {
+    /// スクリプト内での番号 (0 始まり)
+    pub index: usize,
+    /// 文の種類 (例: `select`、`create_table`)。判別できない場合は None
+    pub kind: Option<&'static str>,
+    /// 文のソーステキスト (前後の空白・コメントと区切りの `;` は含まない)
+    pub text: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+    /// 構文エラーなくパースできたか
+    pub valid: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// スクリプトを文ごとに分割し、それぞれを独立にパースする
+/// ある文の構文エラーが他の文の診断を隠さないよう、分割はツリーではなく字句で行う
+pub fn split_statements(src: &str, encoding: PositionEncoding) -> Vec<Statement> {
+    statement_spans(src)
+        .into_iter()
+        .enumerate()
+        .map(|(index, span)| {
+            let tree = span.parse(src);
+            let diagnostics = collect_diagnostics(&tree, src, encoding);
+
+            Statement {
+                index,
+                kind: statement_kind(&tree),
+                text: src[span.start..span.end].to_string(),
+                range: SourceRange::from_bytes(src, span.start, span.end, encoding),
+                valid: diagnostics.is_empty(),
+                diagnostics,
+            }
+        })
+        .collect()
+}
+
+/// ツリーの最初の文の種類
+pub fn statement_kind(tree: &Tree) -> Option<&'static str> {
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let statement = root
+        .named_children(&mut cursor)
+        .find(|child| !child.is_extra())?;
+    statement_kind_of(statement)
+}
+
+/// 文のノードの種類から `_statement` を除いた名前 (例: `select_statement` -> `select`)
+pub fn statement_kind_of(node: Node) -> Option<&'static str> {
+    node.kind().strip_suffix("_statement")
+}
+
+/// スクリプト中の文 1 つのバイト範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatementSpan {
+    /// 前後の空白とコメントを含まない範囲
+    pub start: usize,
+    pub end: usize,
+    /// 文を終える `;` の位置
+    pub terminator: Option<usize>,
+}
+
+impl StatementSpan {
+    /// 区切りの `;` を含めて文をパースする (ノードの位置はスクリプト全体での位置)
+    pub fn parse(&self, src: &str) -> Tree {
+        let end = self
+            .terminator
+            .map_or(self.end, |terminator| terminator + 1);
+        parse_range(src, self.start, end)
+    }
+}
+
+/// 文のバイト範囲の一覧
+/// 文字列・引用符付き識別子・コメント・ドル引用の中の `;` では区切らない
+/// 空の文は除く
+pub fn statement_spans(src: &str) -> Vec<StatementSpan> {
+    let bytes = src.as_bytes();
+    let mut spans = Vec::new();
+    // 現在の文で、コメント以外のトークンが始まった位置と終わった位置
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let token_end = match bytes[i] {
+            b';' => {
+                if let Some(start) = start.take() {
+                    spans.push(StatementSpan {
+                        start,
+                        end,
+                        terminator: Some(i),
+                    });
+                }
+                i += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = find_from(bytes, i, b"\n").map_or(bytes.len(), |p| p + 1);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            }
+            b'\'' | b'"' | b'`' => skip_quoted(bytes, i),
+            b'$' => skip_dollar_quoted(bytes, i).unwrap_or(i + 1),
+            _ => i + 1,
+        };
+
+        start.get_or_insert(i);
+        end = token_end;
+        i = token_end;
+    }
+
+    if let Some(start) = start {
+        spans.push(StatementSpan {
+            start,
+            end,
+            terminator: None,
+        });
+    }
+
+    spans
+}
+
+/// start..end の範囲の字句の範囲の一覧
+/// 空白とコメントは除き、文字列・引用符付き識別子・ドル引用は 1 つの字句とする
+/// 単語 (英数字と `_`) 以外の記号は 1 文字ずつ区切る
+pub fn tokens(src: &str, start: usize, end: usize) -> Vec<Range<usize>> {
+    let bytes = &src.as_bytes()[..end];
+    let mut tokens = Vec::new();
+    let mut i = start;
+
+    while i < bytes.len() {
+        let token_end = match bytes[i] {
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = find_from(bytes, i, b"\n").map_or(bytes.len(), |p| p + 1);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            }
+            b'\'' | b'"' | b'`' => skip_quoted(bytes, i),
+            b'$' => skip_dollar_quoted(bytes, i).unwrap_or(i + 1),
+            c if is_word_byte(c) => bytes[i..]
+                .iter()
+                .position(|b| !is_word_byte(*b))
+                .map_or(bytes.len(), |p| p + i),
+            _ => i + 1,
+        };
+
+        tokens.push(i..token_end.min(end));
+        i = token_end;
+    }
+
+    tokens
+}
+
+fn is_word_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii()
+}
+
+fn find_from(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|p| p + from)
+}
+
+/// `/* ... */` を読み飛ばす (PostgreSQL と同様に入れ子を許す)
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// 引用符で囲まれた文字列や識別子を読み飛ばす (引用符の二重化によるエスケープを許す)
+/// `E'...'` の文字列では、バックスラッシュが次の文字をエスケープする
+fn skip_quoted(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let escapes = quote == b'\'' && is_escape_string_prefix(bytes, start);
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// 引用符の直前が、識別子の一部ではない `E` か
+fn is_escape_string_prefix(bytes: &[u8], quote: usize) -> bool {
+    quote >= 1
+        && matches!(bytes[quote - 1], b'E' | b'e')
+        && (quote < 2 || !is_word_byte(bytes[quote - 2]))
+}
+
+/// `$tag$ ... $tag$` を読み飛ばす。ドル引用でなければ None
+fn skip_dollar_quoted(bytes: &[u8], start: usize) -> Option<usize> {
+    let tag_len = bytes[start + 1..]
+        .iter()
+        .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))?;
+    let tag_end = start + 1 + tag_len;
+    if bytes.get(tag_end) != Some(&b'$') || bytes.get(start + 1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+
+    let tag = &bytes[start..=tag_end];
+    let close = find_from(bytes, tag_end + 1, tag).map_or(bytes.len(), |p| p + tag.len());
+    Some(close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(src: &str) -> Vec<&str> {
+        statement_spans(src)
+            .into_iter()
+            .map(|span| &src[span.start..span.end])
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_semicolons() {
+        assert_eq!(
+            texts("SELECT 1; -- c;\n SELECT 2 ;;"),
+            vec!["SELECT 1", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn ignores_semicolons_in_quotes() {
+        assert_eq!(
+            texts(r#"SELECT 'a;''b', "c;d"; SELECT 2"#),
+            vec![r#"SELECT 'a;''b', "c;d""#, "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn ignores_semicolons_in_dollar_quotes() {
+        let body = "CREATE FUNCTION f() RETURNS int AS $body$ SELECT 1; $x$; $body$ LANGUAGE sql";
+        assert_eq!(
+            texts(&format!("{}; SELECT 2", body)),
+            vec![body, "SELECT 2"]
+        );
+        assert_eq!(
+            texts("SELECT $$;$$; SELECT $1"),
+            vec!["SELECT $$;$$", "SELECT $1"]
+        );
+    }
+
+    #[test]
+    fn ignores_semicolons_in_nested_block_comments() {
+        assert_eq!(
+            texts("SELECT 1 /* a; /* b; */ c; */; SELECT 2"),
+            vec!["SELECT 1", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn escape_strings() {
+        assert_eq!(
+            texts(r"SELECT E'it\'s;' ; SELECT e'\\'; SELECT 3"),
+            vec![r"SELECT E'it\'s;'", r"SELECT e'\\'", "SELECT 3"]
+        );
+        // 識別子の末尾の e は E 文字列の接頭辞ではない
+        assert_eq!(
+            texts(r"SELECT name'\'; SELECT 2"),
+            vec![r"SELECT name'\'", "SELECT 2"]
+        );
+    }
+}
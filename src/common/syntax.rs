@@ -0,0 +1,120 @@
+use tree_sitter::Node;
+
+/// テーブル名や列名などの名前を表すノードの種類
+const NAME_KINDS: &[&str] = &["identifier", "dotted_name"];
+
+/// ノードに対応するソースのテキスト
+pub fn node_text<'a>(node: Node, src: &'a str) -> &'a str {
+    &src[node.byte_range()]
+}
+
+/// ノードが指定したキーワードのトークンか
+/// キーワードは `SELECT` のような匿名ノード (`TRUE` や `NULL` は名前付きノード) として現れる
+pub fn is_keyword(node: Node, keyword: &str) -> bool {
+    node.child_count() == 0 && node.kind().eq_ignore_ascii_case(keyword)
+}
+
+/// ノードが (種類を問わず) キーワードのトークンか
+/// 名前付きの識別子と違い、キーワードは匿名ノードになる
+pub fn is_keyword_token(node: Node, src: &str) -> bool {
+    if node.is_named() || node.child_count() != 0 || node.is_extra() || node.is_missing() {
+        return false;
+    }
+    let text = node_text(node, src);
+    text.chars().any(|c| c.is_ascii_alphabetic())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == '_' || c == ' ')
+}
+
+/// 数値や文字列のリテラルのノードか
+pub fn is_literal(node: Node) -> bool {
+    let kind = node.kind();
+    node.is_named() && (kind == "number" || kind.contains("string") || kind.contains("literal"))
+}
+
+/// ノードが子に指定したキーワードを持つか
+pub fn has_keyword(node: Node, keyword: &str) -> bool {
+    let mut cursor = node.walk();
+    node.children(&mut cursor)
+        .any(|child| is_keyword(child, keyword))
+}
+
+/// 名前 (識別子または修飾された識別子) を表すノードか
+pub fn is_name(node: Node) -> bool {
+    NAME_KINDS.contains(&node.kind())
+}
+
+/// キーワードより後ろにある最初の名前付きの子ノード
+pub fn named_child_after_keyword<'a>(node: Node<'a>, keyword: &str) -> Option<Node<'a>> {
+    let mut cursor = node.walk();
+    let mut children = node.children(&mut cursor);
+    children.find(|child| is_keyword(*child, keyword))?;
+    children.find(|child| child.is_named() && !child.is_extra())
+}
+
+/// 指定した種類の子ノードを返す
+pub fn child_of_kind<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .find(|child| child.kind() == kind)
+}
+
+/// FROM 句に並ぶテーブル (カンマ区切りの各項目) と、それに続く JOIN 句
+pub struct FromItems<'a> {
+    pub tables: Vec<Node<'a>>,
+    pub joins: Vec<Node<'a>>,
+}
+
+/// FROM 句のテーブルと JOIN 句を集める
+/// JOIN 句は FROM 句の子として現れる場合と、FROM 句と並んで文の子として現れる場合がある
+pub fn from_items(from_clause: Node) -> FromItems {
+    let mut cursor = from_clause.walk();
+    let (mut joins, tables): (Vec<_>, Vec<_>) = from_clause
+        .named_children(&mut cursor)
+        .filter(|child| !child.is_extra())
+        .partition(|child| child.kind() == "join_clause");
+    if let Some(statement) = from_clause.parent() {
+        let mut cursor = statement.walk();
+        joins.extend(
+            statement
+                .named_children(&mut cursor)
+                .filter(|child| child.kind() == "join_clause"),
+        );
+    }
+    FromItems { tables, joins }
+}
+
+/// `catalog.schema.table` のような修飾名を `.` で分割する
+/// 二重引用符で囲まれた部分の `.` では分割しない
+pub fn split_qualified_name(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            '.' if !quoted => parts.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current.trim().to_string());
+
+    parts
+}
+
+/// 識別子を比較用に正規化する
+/// 引用符で囲まれていない識別子は小文字にし、囲まれた識別子は引用符を外す
+pub fn normalize_identifier(text: &str) -> String {
+    match text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => text.to_lowercase(),
+    }
+}
@@ -0,0 +1,329 @@
+use serde::Serialize;
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+use super::syntax::{
+    child_of_kind, has_keyword, is_name, named_child_after_keyword, node_text,
+    normalize_identifier, split_qualified_name,
+};
+
+/// 文の中でテーブルがどう使われているか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableUsage {
+    /// FROM / JOIN で読まれる
+    Read,
+    /// INSERT / UPDATE / DELETE / MERGE / TRUNCATE の対象
+    Write,
+    /// CREATE TABLE / CREATE VIEW / SELECT ... INTO で作られる
+    Create,
+    /// DROP で削除される
+    Drop,
+    /// ALTER で変更される
+    Alter,
+}
+
+/// テーブル参照 1 件
+#[derive(Debug, Clone, Serialize)]
+pub struct TableReference {
+    /// 修飾子を除いたテーブル名 (書かれたとおり)
+    pub name: String,
+    pub schema: Option<String>,
+    pub catalog: Option<String>,
+    pub alias: Option<String>,
+    pub usage: TableUsage,
+    /// WITH 句で定義された CTE を参照している場合は true
+    pub is_cte: bool,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// ツリーからテーブル参照を集める
+pub fn extract_tables(tree: &Tree, src: &str, encoding: PositionEncoding) -> Vec<TableReference> {
+    let mut collector = Collector {
+        src,
+        encoding,
+        cte_scopes: Vec::new(),
+        tables: Vec::new(),
+    };
+    collector.visit(tree.root_node());
+
+    collector.tables.sort_by_key(|table| table.range.start_byte);
+    collector.tables
+}
+
+/// WITH 句で定義された CTE の名前 (正規化済み)
+pub fn cte_names(with_clause: Node, src: &str) -> Vec<String> {
+    let mut cursor = with_clause.walk();
+    with_clause
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() == "cte")
+        .filter_map(|cte| {
+            cte.child_by_field_name("name")
+                .or_else(|| cte.named_child(0))
+                .filter(|name| is_name(*name))
+        })
+        .map(|name| normalize_identifier(node_text(name, src)))
+        .collect()
+}
+
+/// テーブルまたはビューを対象とする DDL 文か
+fn is_table_ddl(node: Node) -> bool {
+    node.kind().ends_with("_statement") && (has_keyword(node, "TABLE") || has_keyword(node, "VIEW"))
+}
+
+struct Collector<'a> {
+    src: &'a str,
+    encoding: PositionEncoding,
+    /// 入れ子になった WITH 句ごとの CTE 名
+    cte_scopes: Vec<Vec<String>>,
+    tables: Vec<TableReference>,
+}
+
+impl Collector<'_> {
+    fn visit(&mut self, node: Node) {
+        let with_clause = child_of_kind(node, "with_clause");
+        if let Some(with_clause) = with_clause {
+            self.cte_scopes.push(cte_names(with_clause, self.src));
+        }
+
+        self.collect(node);
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.visit(child);
+        }
+
+        if with_clause.is_some() {
+            self.cte_scopes.pop();
+        }
+    }
+
+    /// 文や句のノードから、テーブル名の位置にある子ノードを取り出す
+    fn collect(&mut self, node: Node) {
+        match node.kind() {
+            "from_clause" => {
+                let usage = match node.parent().map(|parent| parent.kind()) {
+                    Some("delete_statement") => TableUsage::Write,
+                    _ => TableUsage::Read,
+                };
+                let mut cursor = node.walk();
+                for child in node.named_children(&mut cursor) {
+                    self.table_expression(child, usage);
+                }
+            }
+            "join_clause" => {
+                if let Some(table) = named_child_after_keyword(node, "JOIN") {
+                    self.table_expression(table, TableUsage::Read);
+                }
+            }
+            "insert_statement" => {
+                if let Some(table) = named_child_after_keyword(node, "INTO") {
+                    self.table_expression(table, TableUsage::Write);
+                }
+            }
+            "update_statement" => {
+                if let Some(table) = named_child_after_keyword(node, "UPDATE") {
+                    self.table_expression(table, TableUsage::Write);
+                }
+            }
+            "merge_statement" => {
+                if let Some(table) = named_child_after_keyword(node, "INTO") {
+                    self.table_expression(table, TableUsage::Write);
+                }
+                if let Some(table) = named_child_after_keyword(node, "USING") {
+                    self.table_expression(table, TableUsage::Read);
+                }
+            }
+            "truncate_statement" => self.all_names(node, TableUsage::Write),
+            "select_statement" | "select_clause" | "into_clause" => {
+                // SELECT ... INTO new_table
+                if let Some(table) = named_child_after_keyword(node, "INTO") {
+                    self.table_expression(table, TableUsage::Create);
+                }
+            }
+            "drop_statement" if is_table_ddl(node) => self.all_names(node, TableUsage::Drop),
+            kind if kind.starts_with("create_") && is_table_ddl(node) => {
+                self.first_name(node, TableUsage::Create)
+            }
+            kind if kind.starts_with("alter_") && is_table_ddl(node) => {
+                self.first_name(node, TableUsage::Alter)
+            }
+            _ => {}
+        }
+    }
+
+    fn first_name(&mut self, node: Node, usage: TableUsage) {
+        let mut cursor = node.walk();
+        let name = node
+            .named_children(&mut cursor)
+            .find(|child| is_name(*child));
+        if let Some(name) = name {
+            self.push(name, None, usage);
+        }
+    }
+
+    fn all_names(&mut self, node: Node, usage: TableUsage) {
+        let mut cursor = node.walk();
+        let names: Vec<_> = node
+            .named_children(&mut cursor)
+            .filter(|child| is_name(*child))
+            .collect();
+        for name in names {
+            self.push(name, None, usage);
+        }
+    }
+
+    /// FROM 句などに書かれた、別名付きかもしれないテーブル
+    /// サブクエリや関数呼び出しはここでは扱わず、子ノードの走査に任せる
+    fn table_expression(&mut self, node: Node, usage: TableUsage) {
+        if is_name(node) {
+            self.push(node, None, usage);
+            return;
+        }
+
+        if node.kind() == "alias" {
+            let target = node
+                .child_by_field_name("value")
+                .or_else(|| node.named_child(0));
+            let alias = node
+                .child_by_field_name("alias")
+                .or_else(|| node.named_child(node.named_child_count().saturating_sub(1)));
+            if let (Some(target), Some(alias)) = (target, alias)
+                && is_name(target)
+                && target.id() != alias.id()
+            {
+                self.push(target, Some(alias), usage);
+            }
+        }
+    }
+
+    fn push(&mut self, name: Node, alias: Option<Node>, usage: TableUsage) {
+        let mut parts = split_qualified_name(node_text(name, self.src));
+        let table = parts.pop().unwrap_or_default();
+        let schema = parts.pop();
+        let catalog = parts.pop();
+
+        let is_cte = schema.is_none() && {
+            let normalized = normalize_identifier(&table);
+            self.cte_scopes
+                .iter()
+                .any(|scope| scope.contains(&normalized))
+        };
+
+        self.tables.push(TableReference {
+            name: table,
+            schema,
+            catalog,
+            alias: alias.map(|alias| node_text(alias, self.src).to_string()),
+            usage,
+            is_cte,
+            range: SourceRange::new(name, self.src, self.encoding),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tree_sitter_sql::parse;
+
+    /// (名前, スキーマ, 別名, 使われ方, CTE か)
+    type Found = (String, Option<String>, Option<String>, TableUsage, bool);
+
+    fn tables(src: &str) -> Vec<Found> {
+        extract_tables(&parse(src), src, PositionEncoding::Utf8)
+            .into_iter()
+            .map(|table| {
+                (
+                    table.name,
+                    table.schema,
+                    table.alias,
+                    table.usage,
+                    table.is_cte,
+                )
+            })
+            .collect()
+    }
+
+    fn read(name: &str, alias: Option<&str>) -> Found {
+        (
+            name.to_string(),
+            None,
+            alias.map(str::to_string),
+            TableUsage::Read,
+            false,
+        )
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(
+            tables("SELECT * FROM users u JOIN orders AS o ON u.id = o.user_id;"),
+            vec![read("users", Some("u")), read("orders", Some("o"))]
+        );
+    }
+
+    #[test]
+    fn schema_qualified_names() {
+        assert_eq!(
+            tables("SELECT * FROM public.users;"),
+            vec![(
+                "users".to_string(),
+                Some("public".to_string()),
+                None,
+                TableUsage::Read,
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn cte_shadows_table() {
+        let found = tables("WITH users AS (SELECT * FROM accounts) SELECT * FROM users;");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], read("accounts", None));
+        assert_eq!(found[1].0, "users");
+        assert!(found[1].4);
+    }
+
+    #[test]
+    fn schema_qualified_name_is_not_cte() {
+        let found = tables("WITH users AS (SELECT 1) SELECT * FROM public.users;");
+        assert_eq!(found.len(), 1);
+        assert!(!found[0].4);
+    }
+
+    #[test]
+    fn subqueries() {
+        assert_eq!(
+            tables("SELECT * FROM (SELECT id FROM users) AS s;"),
+            vec![read("users", None)]
+        );
+    }
+
+    #[test]
+    fn write_targets() {
+        let usages = |src| {
+            tables(src)
+                .into_iter()
+                .map(|(name, _, _, usage, _)| (name, usage))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            usages("INSERT INTO logs (a) SELECT a FROM events;"),
+            vec![
+                ("logs".to_string(), TableUsage::Write),
+                ("events".to_string(), TableUsage::Read)
+            ]
+        );
+        assert_eq!(
+            usages("UPDATE users SET name = 'a' WHERE id = 1;"),
+            vec![("users".to_string(), TableUsage::Write)]
+        );
+        assert_eq!(
+            usages("DELETE FROM users WHERE id = 1;"),
+            vec![("users".to_string(), TableUsage::Write)]
+        );
+    }
+}
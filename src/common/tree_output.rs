@@ -0,0 +1,347 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Point, Tree, TreeCursor};
+
+use super::position::PositionEncoding;
+
+/// パース結果のツリーを出力する形式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// ダッシュでインデントしたテキスト
+    #[default]
+    Text,
+    /// ノードを入れ子にした JSON
+    Json,
+    /// tree-sitter の `Node::to_sexp` と同じ S 式
+    Sexp,
+}
+
+/// ツリーを出力する際のオプション
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TreeOptions {
+    pub format: OutputFormat,
+    /// true の場合、キーワードや記号などの匿名ノードを出力しない
+    pub named_only: bool,
+    /// true の場合、S 式の葉ノードにソースのテキストを付ける
+    pub include_text: bool,
+    /// テキストと JSON の出力の列番号の単位
+    pub position_encoding: PositionEncoding,
+}
+
+impl TreeOptions {
+    /// ツールのパラメータからオプションを組み立てる
+    /// S 式は to_sexp に合わせ、指定がなければ匿名ノードを出力しない
+    pub fn from_params(
+        format: Option<OutputFormat>,
+        named_only: Option<bool>,
+        include_text: Option<bool>,
+    ) -> Self {
+        let format = format.unwrap_or_default();
+        Self {
+            format,
+            named_only: named_only.unwrap_or(format == OutputFormat::Sexp),
+            include_text: include_text.unwrap_or(false),
+            position_encoding: PositionEncoding::default(),
+        }
+    }
+}
+
+/// 指定された形式でツリーを文字列にする
+pub fn write_tree(tree: &Tree, src: &str, options: TreeOptions) -> String {
+    match options.format {
+        OutputFormat::Text => write_text(tree, src, options),
+        OutputFormat::Json => write_json(tree, src, options),
+        OutputFormat::Sexp => write_sexp(tree, src, options),
+    }
+}
+
+/// 出力対象のノードか
+/// named_only の場合は匿名ノードを読み飛ばすが、to_sexp と同様に MISSING ノードは匿名でも出力する
+fn is_visible(node: Node, options: TreeOptions) -> bool {
+    !options.named_only || node.is_named() || node.is_missing()
+}
+
+/// 出力対象の子ノードへ移動する
+fn goto_first_visible_child(cursor: &mut TreeCursor, options: TreeOptions) -> bool {
+    if !cursor.goto_first_child() {
+        return false;
+    }
+    if is_visible(cursor.node(), options) || goto_next_visible_sibling(cursor, options) {
+        return true;
+    }
+    cursor.goto_parent();
+    false
+}
+
+fn goto_next_visible_sibling(cursor: &mut TreeCursor, options: TreeOptions) -> bool {
+    while cursor.goto_next_sibling() {
+        if is_visible(cursor.node(), options) {
+            return true;
+        }
+    }
+    false
+}
+
+/// 出力する子ノードを持たないか (テキストを出力するか)
+fn is_visible_leaf(cursor: &TreeCursor, options: TreeOptions) -> bool {
+    let node = cursor.node();
+    let mut children = node.walk();
+    !node
+        .children(&mut children)
+        .any(|child| is_visible(child, options))
+}
+
+fn write_text(tree: &Tree, src: &str, options: TreeOptions) -> String {
+    let mut cursor = tree.walk();
+    let mut result = String::new();
+    visit(&mut cursor, 0, src, options, &mut result);
+
+    result
+}
+
+const UNIT: usize = 2;
+
+fn visit(
+    cursor: &mut TreeCursor,
+    depth: usize,
+    src: &str,
+    options: TreeOptions,
+    result: &mut String,
+) {
+    // インデント
+    for _ in 0..(depth * UNIT) {
+        result.push('-');
+    }
+
+    // フィールド名 (例: `where: where_clause`)
+    if let Some(field_name) = cursor.field_name() {
+        result.push_str(field_name);
+        result.push_str(": ");
+    }
+
+    result.push_str(cursor.node().kind());
+
+    if is_visible_leaf(cursor, options) {
+        result.push_str(&format!(
+            " \"{}\"",
+            cursor.node().utf8_text(src.as_bytes()).unwrap()
+        ));
+    }
+
+    let node = cursor.node();
+    result.push_str(&format!(
+        " [{}-{}]\n",
+        format_point(node.start_position(), node.start_byte(), src, options),
+        format_point(node.end_position(), node.end_byte(), src, options)
+    ));
+
+    // 子供を走査
+    if goto_first_visible_child(cursor, options) {
+        visit(cursor, depth + 1, src, options, result);
+        while goto_next_visible_sibling(cursor, options) {
+            visit(cursor, depth + 1, src, options, result);
+        }
+        cursor.goto_parent();
+    }
+}
+
+/// テキスト出力の位置 `(row, column)`。列は position_encoding の単位で数える
+fn format_point(point: Point, byte: usize, src: &str, options: TreeOptions) -> String {
+    let column = options.position_encoding.column(src, byte, point.column);
+    format!("({}, {})", point.row, column)
+}
+
+/// JSON 出力における位置 (0 始まりの行と列)。列は position_encoding の単位で数える
+#[derive(Debug, Serialize)]
+pub struct JsonPoint {
+    pub row: usize,
+    pub column: usize,
+}
+
+impl JsonPoint {
+    fn new(point: Point, byte: usize, src: &str, options: TreeOptions) -> Self {
+        Self {
+            row: point.row,
+            column: options.position_encoding.column(src, byte, point.column),
+        }
+    }
+}
+
+/// JSON 出力における 1 ノード
+#[derive(Debug, Serialize)]
+pub struct JsonNode<'a> {
+    pub kind: &'static str,
+    pub named: bool,
+    pub field_name: Option<&'static str>,
+    /// 出力する子ノードを持たない場合のみソースのテキストを持つ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<&'a str>,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_point: JsonPoint,
+    pub end_point: JsonPoint,
+    pub children: Vec<JsonNode<'a>>,
+}
+
+fn write_json(tree: &Tree, src: &str, options: TreeOptions) -> String {
+    let mut cursor = tree.walk();
+    let root = to_json_node(&mut cursor, src, options);
+
+    serde_json::to_string_pretty(&root).unwrap()
+}
+
+fn to_json_node<'a>(cursor: &mut TreeCursor, src: &'a str, options: TreeOptions) -> JsonNode<'a> {
+    let node = cursor.node();
+
+    let text = if is_visible_leaf(cursor, options) {
+        Some(node.utf8_text(src.as_bytes()).unwrap())
+    } else {
+        None
+    };
+
+    let mut children = Vec::new();
+    if goto_first_visible_child(cursor, options) {
+        children.push(to_json_node(cursor, src, options));
+        while goto_next_visible_sibling(cursor, options) {
+            children.push(to_json_node(cursor, src, options));
+        }
+        cursor.goto_parent();
+    }
+
+    JsonNode {
+        kind: node.kind(),
+        named: node.is_named(),
+        field_name: cursor.field_name(),
+        text,
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        start_point: JsonPoint::new(node.start_position(), node.start_byte(), src, options),
+        end_point: JsonPoint::new(node.end_position(), node.end_byte(), src, options),
+        children,
+    }
+}
+
+fn write_sexp(tree: &Tree, src: &str, options: TreeOptions) -> String {
+    let mut cursor = tree.walk();
+    let mut result = String::new();
+    visit_sexp(&mut cursor, src, options, &mut result);
+
+    result
+}
+
+fn visit_sexp(cursor: &mut TreeCursor, src: &str, options: TreeOptions, result: &mut String) {
+    let node = cursor.node();
+
+    if let Some(field_name) = cursor.field_name() {
+        result.push_str(field_name);
+        result.push_str(": ");
+    }
+
+    // to_sexp と同様に、名前付きノードは括弧で囲み、匿名ノードは引用符で囲む
+    if node.is_missing() {
+        result.push_str("(MISSING ");
+        if node.is_named() {
+            result.push_str(node.kind());
+        } else {
+            push_quoted(node.kind(), result);
+        }
+        result.push(')');
+        return;
+    }
+
+    if !node.is_named() {
+        push_quoted(node.kind(), result);
+        return;
+    }
+
+    // 字句として解釈できなかった文字 (子を持たない ERROR ノード) は、to_sexp と同様に先頭の文字を出力する
+    if node.is_error() && node.child_count() == 0 && node.start_byte() < node.end_byte() {
+        result.push_str("(UNEXPECTED ");
+        let c = src[node.start_byte()..].chars().next().unwrap_or_default();
+        match c {
+            '\0' => result.push_str("'\\0'"),
+            '\n' => result.push_str("'\\n'"),
+            '\t' => result.push_str("'\\t'"),
+            '\r' => result.push_str("'\\r'"),
+            c if c.is_ascii_graphic() || c == ' ' => result.push_str(&format!("'{}'", c)),
+            c => result.push_str(&(c as u32).to_string()),
+        }
+        result.push(')');
+        return;
+    }
+
+    result.push('(');
+    result.push_str(node.kind());
+
+    if options.include_text && is_visible_leaf(cursor, options) {
+        result.push(' ');
+        push_quoted(node.utf8_text(src.as_bytes()).unwrap(), result);
+    }
+
+    if goto_first_visible_child(cursor, options) {
+        loop {
+            result.push(' ');
+            visit_sexp(cursor, src, options, result);
+            if !goto_next_visible_sibling(cursor, options) {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+
+    result.push(')');
+}
+
+/// 文字列を二重引用符で囲み、引用符とバックスラッシュ、改行をエスケープする
+fn push_quoted(text: &str, result: &mut String) {
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tree_sitter_sql::parse;
+
+    fn sexp(src: &str) -> String {
+        let options = TreeOptions::from_params(Some(OutputFormat::Sexp), None, None);
+        write_tree(&parse(src), src, options)
+    }
+
+    #[test]
+    fn sexp_matches_to_sexp() {
+        for src in [
+            "SELECT 1;",
+            "SELECT a, b FROM t WHERE a = 1 ORDER BY b;",
+            "INSERT INTO t (a, b) VALUES (1, 'x');",
+            "SELECT (1",
+            "SELECT * FROM",
+            "SELECT 1 +;",
+            "SELECT a FROM t WHERE ;",
+            "SELECT ` FROM t;",
+        ] {
+            let tree = parse(src);
+            assert_eq!(sexp(src), tree.root_node().to_sexp(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn sexp_keeps_missing_nodes_when_named_only() {
+        let src = "SELECT (1";
+        let tree = parse(src);
+        assert!(tree.root_node().has_error());
+        assert!(tree.root_node().to_sexp().contains("MISSING"));
+        assert!(sexp(src).contains("MISSING"));
+    }
+}
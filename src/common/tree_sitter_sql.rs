@@ -1,9 +1,67 @@
-use tree_sitter::{Tree, TreeCursor};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use rmcp::{Error as McpError, ServerHandler, model::*, tool};
+use notify_debouncer_mini::Debouncer;
+use notify_debouncer_mini::notify::RecommendedWatcher;
+use tree_sitter::Tree;
+
+use rmcp::service::RequestContext;
+use rmcp::{Error as McpError, Peer, RoleServer, ServerHandler, model::*, tool};
+use serde_json::json;
+
+use super::anonymize::anonymize;
+use super::changes::{Edit, changed_ranges};
+use super::classify::classify;
+use super::columns::extract_columns;
+use super::config::Config;
+use super::diagnostics::{collect_diagnostics, render_diagnostics};
+use super::documents::{Documents, TextChange};
+use super::fingerprint::fingerprint;
+use super::fix::fix;
+use super::format::{
+    CommaStyle, FormatError, FormatOptions, LetterCase, format_checked, format_range,
+};
+use super::lint::lint;
+use super::minify::minify;
+use super::parameters::find_parameters;
+use super::position::{line_range, point_at};
+use super::query::execute_query;
+use super::resources::{
+    ResourceKind, document_uri, document_uri_template, file_diagnostics_uri, parse_document_uri,
+    parse_file_diagnostics_uri,
+};
+use super::statements::split_statements;
+use super::tables::extract_tables;
+use super::tree_output::{OutputFormat, TreeOptions, write_tree};
+use super::watch::{FileChange, WatchedFiles, watch};
+use super::workspace::{SymbolKind, Workspace, path_from_uri};
 
 #[derive(Clone)]
-pub struct ParseSqlTool {}
+pub struct ParseSqlTool {
+    config: Arc<Config>,
+    /// open_document で開いたドキュメント
+    documents: Arc<Mutex<Documents>>,
+    /// 購読されているリソースの URI
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    /// 通知を送るクライアント
+    peer: Option<Peer<RoleServer>>,
+    /// ワークスペースの .sql ファイルの索引
+    workspace: Arc<Mutex<Workspace>>,
+    /// ルートが起動時に指定されなかった場合は、クライアントの roots を使う
+    use_client_roots: bool,
+    /// 監視しているディレクトリの .sql ファイル
+    watched: Arc<Mutex<WatchedFiles>>,
+    /// 破棄すると監視が止まるため保持しておく
+    watcher: Arc<Mutex<Option<Debouncer<RecommendedWatcher>>>>,
+}
+
+/// ツールに渡された SQL と、ドキュメントの場合はそのパース済みのツリー
+struct Source {
+    sql: String,
+    tree: Option<Tree>,
+}
 
 #[tool(tool_box)]
 impl ServerHandler for ParseSqlTool {
@@ -13,37 +71,472 @@ impl ServerHandler for ParseSqlTool {
             capabilities: ServerCapabilities::builder()
                 .enable_prompts()
                 .enable_resources()
+                .enable_resources_subscribe()
+                .enable_resources_list_changed()
                 .enable_tools()
                 .build(),
             server_info: Implementation::from_build_env(),
-            instructions: Some("This server provides tools to parse SQL statements into a tree structure using future-architect/tree-sitter-sql. Use the 'parse_sql' tool to strictly parse SQL statements, or 'parse_sql_with_error_recovery' to parse and return the tree including ERROR nodes for error recovery.".to_string()),
+            instructions: Some(format!(
+                "This server provides tools to parse SQL statements into a tree structure using future-architect/tree-sitter-sql. Use the 'parse_sql' tool to strictly parse SQL statements (syntax errors are reported with their location), or 'parse_sql_with_error_recovery' to parse and return the tree including ERROR nodes for error recovery. Both tools accept 'output_format' (\"text\", \"json\" or \"sexp\"), 'named_only' to drop keyword and punctuation tokens, and 'include_text' to attach leaf text to the S-expression. Use 'run_query' to search the tree with a tree-sitter query pattern, 'extract_tables' to list the tables a statement touches, 'extract_columns' to list column references resolved to their tables, 'split_statements' to split a script into statements, 'classify_sql' to check whether a script is read-only before running it, 'lint_sql' to find destructive, suspicious or badly styled statements, 'fix_sql' to repair syntax errors that can be fixed without guessing, 'format_sql' to pretty-print sql, 'format_sql_range' to format only the statements in a range, 'minify_sql' to compact sql into a short form, 'fingerprint_sql' to group queries by shape, 'anonymize_sql' to replace literals before sharing a query, 'find_parameters' to list bind parameters with the columns they are compared against, and 'changed_ranges' to see which statements an edit affected. Across the .sql files of the workspace, use 'workspace_symbols' to search defined tables, views and functions, 'find_definition' to find where one is created and 'find_references' to find where it is used. To work on a large script, open it once with 'open_document', update it with 'edit_document' and pass its document_id to the other tools instead of sql; close it with 'close_document'. Each open document is also exposed as the resources sql://doc/{{id}}/tree, sql://doc/{{id}}/diagnostics and sql://doc/{{id}}/tables, which can be subscribed to for updates. When the server watches directories, the syntax errors of each .sql file in them are exposed as the resource sql://diagnostics/{{path}} and updated when the file changes. SQL is parsed as the {} dialect.",
+                self.config.dialect.name()
+            )),
         }
     }
+
+    fn get_peer(&self) -> Option<Peer<RoleServer>> {
+        self.peer.clone()
+    }
+
+    fn set_peer(&mut self, peer: Peer<RoleServer>) {
+        self.peer = Some(peer);
+        // 通知を送れるようになってから監視を始める
+        self.start_watching();
+    }
+
+    async fn on_initialized(&self) {
+        tracing::info!("client initialized");
+        self.index_client_roots().await;
+    }
+
+    async fn on_roots_list_changed(&self) {
+        self.index_client_roots().await;
+    }
+
+    async fn list_resources(
+        &self,
+        _request: PaginatedRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let documents = self.documents.lock().unwrap();
+        let mut resources: Vec<_> = documents
+            .ids()
+            .into_iter()
+            .flat_map(|document_id| {
+                ResourceKind::ALL.into_iter().map(move |kind| {
+                    RawResource {
+                        description: Some(kind.description().to_string()),
+                        mime_type: Some(kind.mime_type(self.config.output_format).to_string()),
+                        ..RawResource::new(
+                            document_uri(document_id, kind),
+                            format!("document {} {}", document_id, kind.name()),
+                        )
+                    }
+                    .no_annotation()
+                })
+            })
+            .collect();
+
+        let watched = self.watched.lock().unwrap();
+        resources.extend(watched.files.keys().map(|path| {
+            RawResource {
+                description: Some("syntax errors of a watched sql file".to_string()),
+                mime_type: Some("application/json".to_string()),
+                ..RawResource::new(
+                    file_diagnostics_uri(path),
+                    format!("{} diagnostics", path.display()),
+                )
+            }
+            .no_annotation()
+        }));
+
+        Ok(ListResourcesResult {
+            next_cursor: None,
+            resources,
+        })
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: PaginatedRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        let mut resource_templates: Vec<_> = ResourceKind::ALL
+            .into_iter()
+            .map(|kind| {
+                RawResourceTemplate {
+                    uri_template: document_uri_template(kind),
+                    name: format!("document {}", kind.name()),
+                    description: Some(kind.description().to_string()),
+                    mime_type: Some(kind.mime_type(self.config.output_format).to_string()),
+                }
+                .no_annotation()
+            })
+            .collect();
+        resource_templates.push(
+            RawResourceTemplate {
+                uri_template: "sql://diagnostics/{path}".to_string(),
+                name: "watched file diagnostics".to_string(),
+                description: Some(
+                    "syntax errors of a .sql file in a watched directory, by its absolute path without the leading '/'".to_string(),
+                ),
+                mime_type: Some("application/json".to_string()),
+            }
+            .no_annotation(),
+        );
+
+        Ok(ListResourceTemplatesResult {
+            next_cursor: None,
+            resource_templates,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        Ok(ReadResourceResult {
+            contents: vec![self.read_resource_contents(&request.uri)?],
+        })
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        // 存在しないリソースは購読できない
+        self.read_resource_contents(&request.uri)?;
+        self.subscriptions.lock().unwrap().insert(request.uri);
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.subscriptions.lock().unwrap().remove(&request.uri);
+        Ok(())
+    }
 }
 
 #[tool(tool_box)]
 impl ParseSqlTool {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: Arc::new(config),
+            documents: Arc::default(),
+            subscriptions: Arc::default(),
+            peer: None,
+            workspace: Arc::default(),
+            use_client_roots: true,
+            watched: Arc::default(),
+            watcher: Arc::default(),
+        }
+    }
+
+    /// ワークスペースのルートを指定し、その下の .sql ファイルを索引する
+    pub fn with_roots(mut self, roots: Vec<PathBuf>) -> Self {
+        if !roots.is_empty() {
+            let workspace = Workspace::index(
+                roots,
+                self.config.limits.max_sql_bytes,
+                self.config.position_encoding,
+            );
+            tracing::info!("indexed {} sql files", workspace.files.len());
+            self.workspace = Arc::new(Mutex::new(workspace));
+            self.use_client_roots = false;
+        }
+        self
+    }
+
+    /// クライアントの roots を取得し、ワークスペースを索引し直す
+    async fn index_client_roots(&self) {
+        let Some(peer) = self.peer.clone().filter(|_| self.use_client_roots) else {
+            return;
+        };
+        if peer.peer_info().capabilities.roots.is_none() {
+            return;
+        }
+
+        let roots = match peer.list_roots().await {
+            Ok(result) => result.roots,
+            Err(e) => {
+                tracing::warn!("fail to list roots: {:?}", e);
+                return;
+            }
+        };
+        let roots: Vec<_> = roots
+            .iter()
+            .filter_map(|root| path_from_uri(&root.uri))
+            .collect();
+        let max_sql_bytes = self.config.limits.max_sql_bytes;
+        let encoding = self.config.position_encoding;
+        match tokio::task::spawn_blocking(move || Workspace::index(roots, max_sql_bytes, encoding))
+            .await
+        {
+            Ok(workspace) => {
+                tracing::info!("indexed {} sql files", workspace.files.len());
+                *self.workspace.lock().unwrap() = workspace;
+            }
+            Err(e) => tracing::warn!("fail to index workspace: {:?}", e),
+        }
+    }
+
+    /// sql または document_id のどちらか一方から、対象の SQL を取り出す
+    fn source(&self, sql: Option<String>, document_id: Option<String>) -> Result<Source, McpError> {
+        match (sql, document_id) {
+            (Some(sql), None) => {
+                self.check_size(&sql)?;
+                Ok(Source { sql, tree: None })
+            }
+            (None, Some(document_id)) => {
+                let documents = self.documents.lock().unwrap();
+                let document = documents.get(&document_id).ok_or_else(|| {
+                    McpError::invalid_params(format!("unknown document id: {}", document_id), None)
+                })?;
+                Ok(Source {
+                    sql: document.text.clone(),
+                    tree: Some(document.tree.clone()),
+                })
+            }
+            _ => Err(McpError::invalid_params(
+                "specify either sql or document_id",
+                None,
+            )),
+        }
+    }
+
+    /// SQL が設定の上限を超えていないか確認する
+    fn check_size(&self, sql: &str) -> Result<(), McpError> {
+        let max_sql_bytes = self.config.limits.max_sql_bytes;
+        if sql.len() > max_sql_bytes {
+            return Err(McpError::invalid_params(
+                format!(
+                    "sql is too large: {} bytes (limit is {} bytes)",
+                    sql.len(),
+                    max_sql_bytes
+                ),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// リソースの内容を作る
+    fn read_resource_contents(&self, uri: &str) -> Result<ResourceContents, McpError> {
+        match parse_file_diagnostics_uri(uri) {
+            Some(path) => self.read_file_diagnostics(uri, &path),
+            None => self.read_document_resource(uri),
+        }
+    }
+
+    /// `sql://diagnostics/{path}` の内容を作る
+    fn read_file_diagnostics(
+        &self,
+        uri: &str,
+        path: &std::path::Path,
+    ) -> Result<ResourceContents, McpError> {
+        let watched = self.watched.lock().unwrap();
+        let file = watched.files.get(path).ok_or_else(|| {
+            McpError::resource_not_found(format!("unknown resource: {}", uri), None)
+        })?;
+        let text = json!({
+            "path": path.display().to_string(),
+            "valid": file.diagnostics.is_empty(),
+            "diagnostics": file.diagnostics,
+        })
+        .to_string();
+
+        Ok(ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some("application/json".to_string()),
+            text,
+        })
+    }
+
+    /// `sql://doc/{id}/{kind}` の内容を作る
+    fn read_document_resource(&self, uri: &str) -> Result<ResourceContents, McpError> {
+        let not_found = || McpError::resource_not_found(format!("unknown resource: {}", uri), None);
+        let (document_id, kind) = parse_document_uri(uri).ok_or_else(not_found)?;
+        let documents = self.documents.lock().unwrap();
+        let document = documents.get(&document_id).ok_or_else(not_found)?;
+        let (sql, tree) = (&document.text, &document.tree);
+
+        let text = match kind {
+            ResourceKind::Tree => write_tree(tree, sql, self.tree_options(None, None, None)),
+            ResourceKind::Diagnostics => json!({
+                "version": document.version,
+                "diagnostics": collect_diagnostics(tree, sql, self.config.position_encoding),
+            })
+            .to_string(),
+            ResourceKind::Tables => json!({
+                "version": document.version,
+                "tables": extract_tables(tree, sql, self.config.position_encoding),
+            })
+            .to_string(),
+        };
+
+        Ok(ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some(kind.mime_type(self.config.output_format).to_string()),
+            text,
+        })
+    }
+
+    /// 購読されているドキュメントのリソースに更新を通知する
+    fn notify_document_updated(&self, document_id: &str) {
+        let Some(peer) = self.peer.clone() else {
+            return;
+        };
+        let subscriptions = self.subscriptions.lock().unwrap();
+        let uris: Vec<_> = ResourceKind::ALL
+            .into_iter()
+            .map(|kind| document_uri(document_id, kind))
+            .filter(|uri| subscriptions.contains(uri))
+            .collect();
+        send_resource_updated(peer, uris);
+    }
+
+    /// ドキュメントを開いたり閉じたりして、リソースの一覧が変わったことを通知する
+    fn notify_resource_list_changed(&self) {
+        if let Some(peer) = self.peer.clone() {
+            send_resource_list_changed(peer);
+        }
+    }
+
+    /// 設定されたディレクトリの .sql ファイルを読み込み、変更の監視を始める
+    /// 読み込みは初期化を止めないよう別のスレッドで行い、終わってから監視を始める
+    /// 変更があると前のツリーを再利用してパースし直し、構文エラーのリソースの更新を通知する
+    fn start_watching(&self) {
+        let config = self.config.watch.clone();
+        if config.dirs.is_empty() {
+            return;
+        }
+        let dirs: Vec<_> = config
+            .dirs
+            .iter()
+            .filter_map(|dir| match dir.canonicalize() {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    tracing::warn!("fail to watch {}: {}", dir.display(), e);
+                    None
+                }
+            })
+            .collect();
+        let max_sql_bytes = self.config.limits.max_sql_bytes;
+        let encoding = self.config.position_encoding;
+
+        let watched = self.watched.clone();
+        let watcher = self.watcher.clone();
+        let subscriptions = self.subscriptions.clone();
+        let peer = self.peer.clone();
+        tokio::spawn(async move {
+            let scan_dirs = dirs.clone();
+            let files = match tokio::task::spawn_blocking(move || {
+                WatchedFiles::scan(&scan_dirs, max_sql_bytes, encoding)
+            })
+            .await
+            {
+                Ok(files) => files,
+                Err(e) => {
+                    tracing::warn!("fail to scan watched directories: {:?}", e);
+                    return;
+                }
+            };
+            tracing::info!("watching {} sql files", files.files.len());
+            *watched.lock().unwrap() = files;
+            if let Some(peer) = peer.clone() {
+                send_resource_list_changed(peer);
+            }
+
+            let runtime = tokio::runtime::Handle::current();
+            let handler = move |paths: Vec<PathBuf>| {
+                let changes: Vec<_> = {
+                    let mut watched = watched.lock().unwrap();
+                    paths.iter().flat_map(|path| watched.update(path)).collect()
+                };
+                let Some(peer) = peer.clone().filter(|_| !changes.is_empty()) else {
+                    return;
+                };
+
+                let subscriptions = subscriptions.lock().unwrap();
+                let uris: Vec<_> = changes
+                    .iter()
+                    .map(|(path, _)| file_diagnostics_uri(path))
+                    .filter(|uri| subscriptions.contains(uri))
+                    .collect();
+                let list_changed = changes
+                    .iter()
+                    .any(|(_, change)| *change != FileChange::Modified);
+
+                let _guard = runtime.enter();
+                if list_changed {
+                    send_resource_list_changed(peer.clone());
+                }
+                send_resource_updated(peer, uris);
+            };
+
+            match watch(&dirs, Duration::from_millis(config.debounce_ms), handler) {
+                Ok(debouncer) => *watcher.lock().unwrap() = Some(debouncer),
+                Err(e) => tracing::warn!("fail to watch directories: {:?}", e),
+            }
+        });
+    }
+
+    fn tree_options(
+        &self,
+        output_format: Option<OutputFormat>,
+        named_only: Option<bool>,
+        include_text: Option<bool>,
+    ) -> TreeOptions {
+        TreeOptions {
+            position_encoding: self.config.position_encoding,
+            ..TreeOptions::from_params(
+                Some(output_format.unwrap_or(self.config.output_format)),
+                named_only,
+                include_text,
+            )
+        }
     }
 
     #[tool(description = "Parse sql")]
     /// SQL をパースしてツリーを表現した文字列を返す
-    /// パースに失敗した場合はエラーを返す
+    /// パースに失敗した場合は構文エラーの一覧と、エラー箇所を示すコードフレームをエラーとして返す
+    /// fix_sql と同じ修正の結果を添える (一部しか直せない場合は valid が false になる)
     pub fn parse_sql(
+        &self,
         #[tool(param)]
-        #[schemars(description = "sql text to parse")]
-        sql: String,
+        #[schemars(description = "sql text to parse (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(
+            description = "output format of the tree: \"text\" (default), \"json\" or \"sexp\""
+        )]
+        output_format: Option<OutputFormat>,
+        #[tool(param)]
+        #[schemars(description = "omit anonymous nodes (keywords and punctuation) from the tree")]
+        named_only: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = "attach leaf text to the nodes of the \"sexp\" output")]
+        include_text: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
-        let tree = parse(&sql);
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
 
         if tree.root_node().has_error() {
-            Err(McpError::invalid_params(
-                "Failed to parse sql".to_string(),
-                None,
-            ))
+            let diagnostics = collect_diagnostics(&tree, &sql, self.config.position_encoding);
+            let fix = fix(&sql, self.config.position_encoding);
+
+            Ok(CallToolResult::error(vec![
+                Content::json(json!({
+                    "message": "Failed to parse sql",
+                    "diagnostics": diagnostics,
+                    "fix": fix,
+                }))?,
+                Content::text(render_diagnostics(&sql, &diagnostics)),
+            ]))
         } else {
-            let result = write_tree(&tree, &sql);
+            let result = write_tree(
+                &tree,
+                &sql,
+                self.tree_options(output_format, named_only, include_text),
+            );
 
             Ok(CallToolResult::success(vec![Content::text(result)]))
         }
@@ -53,62 +546,706 @@ impl ParseSqlTool {
     /// SQL をパースしてツリーを表現した文字列を返す
     /// パースは失敗せず、ERROR ノードを含めてツリーを表現した文字列を返す
     pub fn parse_sql_with_error_recovery(
+        &self,
         #[tool(param)]
-        #[schemars(description = "sql text to parse")]
-        sql: String,
+        #[schemars(description = "sql text to parse (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(
+            description = "output format of the tree: \"text\" (default), \"json\" or \"sexp\""
+        )]
+        output_format: Option<OutputFormat>,
+        #[tool(param)]
+        #[schemars(description = "omit anonymous nodes (keywords and punctuation) from the tree")]
+        named_only: Option<bool>,
+        #[tool(param)]
+        #[schemars(description = "attach leaf text to the nodes of the \"sexp\" output")]
+        include_text: Option<bool>,
     ) -> Result<CallToolResult, McpError> {
-        let tree = parse(&sql);
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
 
-        let result = write_tree(&tree, &sql);
+        let result = write_tree(
+            &tree,
+            &sql,
+            self.tree_options(output_format, named_only, include_text),
+        );
 
         Ok(CallToolResult::success(vec![Content::text(result)]))
     }
-}
 
-fn parse(sql: &str) -> Tree {
-    let language = tree_sitter_sql::language();
-    let mut parser = tree_sitter::Parser::new();
-    parser.set_language(language).unwrap();
+    #[tool(
+        description = "Run a tree-sitter query pattern against the parsed sql and return the captures of every match"
+    )]
+    /// SQL をパースし、tree-sitter のクエリにマッチしたノードを返す
+    /// クエリが不正な場合は、QueryError の行・列・種類をエラーとして返す
+    pub fn run_query(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql text to parse (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(
+            description = "tree-sitter query pattern, e.g. \"(where_clause (binary_expression) @condition)\""
+        )]
+        query: String,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
 
-    let tree = parser.parse(&sql, None).unwrap();
+        let tree = tree.unwrap_or_else(|| parse(&sql));
 
-    tree
-}
+        // ほかの位置と同じく、行と列は 1 始まりにする
+        let matches =
+            execute_query(&tree, &sql, &query, self.config.position_encoding).map_err(|e| {
+                McpError::invalid_params(
+                    format!(
+                        "Invalid query at line {}, column {}: {:?} error: {}",
+                        e.row + 1,
+                        e.column + 1,
+                        e.kind,
+                        e.message
+                    ),
+                    Some(json!({
+                        "line": e.row + 1,
+                        "column": e.column + 1,
+                        "offset": e.offset,
+                        "kind": format!("{:?}", e.kind),
+                        "message": e.message,
+                    })),
+                )
+            })?;
 
-fn write_tree(tree: &Tree, src: &str) -> String {
-    let mut cursor = tree.walk();
-    let mut result = String::new();
-    visit(&mut cursor, 0, &src, &mut result);
+        Ok(CallToolResult::success(vec![Content::json(matches)?]))
+    }
 
-    result
-}
+    #[tool(
+        description = "Extract the tables referenced by the sql with their schema qualifier, alias and whether they are read, written, created, dropped or altered"
+    )]
+    /// SQL をパースし、参照しているテーブルの一覧を返す
+    /// WITH 句で定義された CTE への参照は is_cte で区別する
+    pub fn extract_tables(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql text to parse (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
 
-const UNIT: usize = 2;
+        let tables = extract_tables(&tree, &sql, self.config.position_encoding);
 
-fn visit(cursor: &mut TreeCursor, depth: usize, src: &str, result: &mut String) {
-    // インデント
-    for _ in 0..(depth * UNIT) {
-        result.push_str("-");
+        Ok(CallToolResult::success(vec![Content::json(tables)?]))
     }
 
-    result.push_str(&format!("{}", cursor.node().kind()));
+    #[tool(
+        description = "Extract the column references in the sql with their qualifier, the table the qualifier resolves to and the clause they appear in"
+    )]
+    /// SQL をパースし、列参照の一覧を返す
+    /// 修飾子の別名は、サブクエリや CTE のスコープを考慮して FROM / JOIN のテーブルに解決する
+    pub fn extract_columns(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql text to parse (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
 
-    if cursor.node().child_count() == 0 {
-        result.push_str(&format!(" \"{}\"", cursor.node().utf8_text(src.as_bytes()).unwrap()));
+        let columns = extract_columns(&tree, &sql, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(columns)?]))
     }
 
-    result.push_str(&format!(
-        " [{}-{}]\n",
-        cursor.node().start_position(),
-        cursor.node().end_position()
-    ));
+    #[tool(
+        description = "Split a sql script into top-level statements and return each statement's range, source text, kind and syntax diagnostics"
+    )]
+    /// SQL スクリプトを文ごとに分割して返す
+    /// 文ごとに独立してパースするので、途中の文の構文エラーが他の文の結果に影響しない
+    pub fn split_statements(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to split (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let statements = split_statements(&sql, self.config.position_encoding);
 
-    // 子供を走査
-    if cursor.goto_first_child() {
-        visit(cursor, depth + 1, src, result);
-        while cursor.goto_next_sibling() {
-            visit(cursor, depth + 1, src, result);
+        Ok(CallToolResult::success(vec![Content::json(statements)?]))
+    }
+
+    #[tool(
+        description = "Classify each statement as DQL, DML, DDL, DCL or transaction control and report whether the whole script is read-only. Calls to any function other than known side-effect-free built-ins count as writes, including user-defined functions, whose bodies cannot be inspected. A script with no statements is not read-only"
+    )]
+    /// SQL スクリプトの各文を分類し、全体が読み取り専用かを返す
+    /// CTE の中の DML や SELECT ... INTO、副作用のない組み込み関数以外の呼び出しは読み取り専用とみなさない
+    pub fn classify_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to classify (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let classification = classify(&sql, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(
+            classification,
+        )?]))
+    }
+
+    #[tool(
+        description = "Lint sql for dangerous statements (UPDATE/DELETE without WHERE, DROP, TRUNCATE, always-true conditions, cartesian joins) and style issues (keyword case, SELECT *, aliases without AS, positional GROUP BY, mixed join styles, unqualified columns in joins). Each finding has a rule_id; rules can be configured or turned off in the [lint] section of the config file"
+    )]
+    /// SQL スクリプトにリントを適用し、指摘の一覧を返す
+    pub fn lint_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to lint (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let findings = lint(&sql, &self.config.lint, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(findings)?]))
+    }
+
+    #[tool(
+        description = "Fix syntax errors that can be repaired without guessing: unbalanced trailing parentheses, a trailing comma before FROM or ')', and a missing ';' between statements. Returns each fix as a text edit (range and replacement) on the original sql, the fixed sql, and the syntax errors left in the fixed sql. Fixes are tried in order and one is kept only when it reduces the syntax errors of its statement"
+    )]
+    /// 推測なしに直せる構文エラーの修正を返す
+    pub fn fix_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to fix (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let fix = fix(&sql, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(fix)?]))
+    }
+
+    #[tool(
+        description = "Format sql from its syntax tree: one clause per line, lists and conditions wrapped when a clause exceeds the line width, subqueries indented. Comments are kept and only whitespace and keyword case change; the result is checked to parse to the same tree. Fails if the sql has syntax errors. Omitted options default to the [format] section of the config file"
+    )]
+    /// SQL を整形した文字列を返す
+    /// 構文エラーがある場合と、整形結果のツリーが元と異なる場合はエラーを返す
+    #[allow(clippy::too_many_arguments)]
+    pub fn format_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to format (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "number of spaces per indentation level (default 2)")]
+        indent_width: Option<usize>,
+        #[tool(param)]
+        #[schemars(
+            description = "case of keywords: \"upper\" (default), \"lower\" or \"preserve\""
+        )]
+        keyword_case: Option<LetterCase>,
+        #[tool(param)]
+        #[schemars(
+            description = "position of commas in wrapped lists: \"last\" (end of line, default) or \"first\" (start of line)"
+        )]
+        comma_style: Option<CommaStyle>,
+        #[tool(param)]
+        #[schemars(
+            description = "clauses longer than this are wrapped one item per line (default 80)"
+        )]
+        line_width: Option<usize>,
+        #[tool(param)]
+        #[schemars(
+            description = "start every clause (FROM, WHERE, ...) on its own line (default true); if false, only statements that do not fit on one line are split"
+        )]
+        clause_per_line: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let defaults = self.config.format;
+        let options = FormatOptions {
+            indent_width: indent_width.unwrap_or(defaults.indent_width),
+            keyword_case: keyword_case.unwrap_or(defaults.keyword_case),
+            comma_style: comma_style.unwrap_or(defaults.comma_style),
+            line_width: line_width.unwrap_or(defaults.line_width),
+            clause_per_line: clause_per_line.unwrap_or(defaults.clause_per_line),
+        };
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
+        match format_checked(&tree, &sql, &options) {
+            Ok(formatted) => Ok(CallToolResult::success(vec![Content::text(formatted)])),
+            Err(FormatError::SyntaxError) => {
+                let diagnostics = collect_diagnostics(&tree, &sql, self.config.position_encoding);
+
+                Ok(CallToolResult::error(vec![
+                    Content::json(json!({
+                        "message": "Failed to parse sql; only sql without syntax errors can be formatted",
+                        "diagnostics": diagnostics,
+                    }))?,
+                    Content::text(render_diagnostics(&sql, &diagnostics)),
+                ]))
+            }
+            Err(e) => Err(McpError::internal_error(
+                format!("{}; the sql was left unformatted", e.message()),
+                None,
+            )),
+        }
+    }
+
+    #[tool(
+        description = "Format only the top-level statements of a script that overlap a byte range or a line range (one clause per line, consistent keyword case). Everything outside those statements is left byte-for-byte identical, and formatting twice changes nothing. An empty range formats the statement at that position. With check=true, only returns whether anything would change and the unified diff"
+    )]
+    /// 範囲と重なる文だけを整形する
+    #[allow(clippy::too_many_arguments)]
+    pub fn format_sql_range(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "start of the range in bytes (0-based)")]
+        start_byte: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = "end of the range in bytes (0-based, exclusive)")]
+        end_byte: Option<usize>,
+        #[tool(param)]
+        #[schemars(description = "first line of the range (1-based), instead of start_byte")]
+        start_line: Option<usize>,
+        #[tool(param)]
+        #[schemars(
+            description = "last line of the range (1-based, inclusive), instead of end_byte; defaults to start_line"
+        )]
+        end_line: Option<usize>,
+        #[tool(param)]
+        #[schemars(
+            description = "only report whether formatting would change the sql, with a unified diff"
+        )]
+        check: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let range = match (start_byte, end_byte, start_line, end_line) {
+            (Some(start), end, None, None) => start..end.unwrap_or(start),
+            (None, None, Some(start), end) => line_range(&sql, start, end.unwrap_or(start)),
+            _ => {
+                return Err(McpError::invalid_params(
+                    "specify either start_byte (and end_byte) or start_line (and end_line)",
+                    None,
+                ));
+            }
+        };
+        if range.start > range.end || range.end > sql.len() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "invalid range {}..{} for sql of {} bytes",
+                    range.start,
+                    range.end,
+                    sql.len()
+                ),
+                None,
+            ));
+        }
+
+        let options = FormatOptions {
+            clause_per_line: true,
+            ..self.config.format
+        };
+        let result = format_range(&sql, range, &options, self.config.position_encoding);
+
+        if check.unwrap_or(false) {
+            return Ok(CallToolResult::success(vec![Content::json(json!({
+                "changed": result.changed,
+                "diff": result.diff,
+            }))?]));
+        }
+        Ok(CallToolResult::success(vec![Content::json(result)?]))
+    }
+
+    #[tool(
+        description = "Minify sql into a compact form: comments are removed and whitespace is reduced to what is needed to keep tokens apart. String literals and quoted identifiers are kept exactly as written. The result is checked to parse to the same tree"
+    )]
+    /// コメントと余分な空白を除いた SQL を返す
+    /// 結果のツリーが元と異なる場合はエラーを返す
+    pub fn minify_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to minify (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
+
+        match minify(&tree, &sql) {
+            Some(minified) => Ok(CallToolResult::success(vec![Content::text(minified)])),
+            None => Err(McpError::internal_error(
+                "minifying changed the syntax tree; the sql was left unminified",
+                None,
+            )),
+        }
+    }
+
+    #[tool(
+        description = "Fingerprint each statement of a script by its shape, like pg_stat_statements query ids: literals (numbers, strings, IN-lists of literals) are replaced with '?', comments are removed, whitespace is collapsed and keywords are upper-cased. Returns the normalized text and a stable 64-bit hash per statement"
+    )]
+    /// 文ごとに、リテラルを除いた正規化テキストとそのハッシュを返す
+    pub fn fingerprint_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to fingerprint (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let fingerprints = fingerprint(&sql, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(fingerprints)?]))
+    }
+
+    #[tool(
+        description = "Anonymize sql before sharing it: string, number and date literals (and identifiers if requested) are replaced with consistent pseudonyms, so the same value always gets the same pseudonym. Returns the anonymized sql, the mapping from pseudonyms back to the original values, and whether the result parses cleanly"
+    )]
+    /// リテラルを仮名に置き換えた SQL と、元の値との対応を返す
+    /// 元の SQL が構文エラーなくパースできるのに結果がパースできない場合はエラーを返す
+    pub fn anonymize_sql(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to anonymize (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+        #[tool(param)]
+        #[schemars(
+            description = "also replace table, column and alias names (function and type names are kept)"
+        )]
+        identifiers: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        let Source { sql, tree } = self.source(sql, document_id)?;
+
+        let tree = tree.unwrap_or_else(|| parse(&sql));
+        let anonymized = anonymize(&tree, &sql, identifiers.unwrap_or(false));
+
+        if !tree.root_node().has_error() && !anonymized.valid {
+            return Err(McpError::internal_error(
+                "anonymized sql does not parse",
+                None,
+            ));
+        }
+
+        Ok(CallToolResult::success(vec![Content::json(anonymized)?]))
+    }
+
+    #[tool(
+        description = "List the bind parameters of a sql script (?, $1, :name, @name and %(name)s) with their positions and the column or expression each one is compared against, e.g. 'users.id' for 'users.id = $1', including the table the column belongs to when it can be resolved. Parameters inside strings, comments and '::' casts are ignored"
+    )]
+    /// SQL のバインド変数と、それぞれが比較される列や式を返す
+    pub fn find_parameters(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql script to search for bind parameters (or pass document_id)")]
+        sql: Option<String>,
+        #[tool(param)]
+        #[schemars(description = "id of a document opened with open_document, instead of sql")]
+        document_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let sql = self.source(sql, document_id)?.sql;
+
+        let parameters = find_parameters(&sql, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(parameters)?]))
+    }
+
+    #[tool(
+        description = "Compare two versions of a sql script: reparse new_sql incrementally from the tree of old_sql and return the ranges whose syntax changed (tree-sitter's changed_ranges) with the kinds of the nodes in them, and the statements of new_sql touched by the edit. Pass the edit that turned old_sql into new_sql if known; otherwise it is computed from the common prefix and suffix of the two texts"
+    )]
+    /// 古い SQL のツリーから新しい SQL をパースし直し、構文が変わった範囲と影響を受けた文を返す
+    pub fn changed_ranges(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql before the edit")]
+        old_sql: String,
+        #[tool(param)]
+        #[schemars(description = "sql after the edit")]
+        new_sql: String,
+        #[tool(param)]
+        #[schemars(
+            description = "edit that turned old_sql into new_sql: old_sql[start_byte..old_end_byte] was replaced by new_sql[start_byte..new_end_byte]"
+        )]
+        edit: Option<Edit>,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_size(&old_sql)?;
+        self.check_size(&new_sql)?;
+
+        let edit = match edit {
+            Some(edit) => {
+                edit.validate(&old_sql, &new_sql)
+                    .map_err(|message| McpError::invalid_params(message, None))?;
+                edit
+            }
+            None => Edit::between(&old_sql, &new_sql),
+        };
+        let changes = changed_ranges(&old_sql, &new_sql, edit, self.config.position_encoding);
+
+        Ok(CallToolResult::success(vec![Content::json(changes)?]))
+    }
+
+    #[tool(
+        description = "Open sql as a document kept by the server and return its document_id. Other tools accept the document_id instead of sql, and edit_document updates it with incremental reparsing, so large scripts do not need to be resent on every change"
+    )]
+    /// SQL をドキュメントとして開き、その ID を返す
+    pub fn open_document(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "sql text of the document")]
+        sql: String,
+    ) -> Result<CallToolResult, McpError> {
+        self.check_size(&sql)?;
+
+        let mut documents = self.documents.lock().unwrap();
+        let document_id = documents.open(sql);
+        let document = documents.get(&document_id).unwrap();
+        let content = Content::json(json!({
+            "document_id": document_id,
+            "version": document.version,
+            "valid": !document.tree.root_node().has_error(),
+        }))?;
+        drop(documents);
+
+        self.notify_resource_list_changed();
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(
+        description = "Apply text changes to an open document and reparse it incrementally from the previous tree. Each change replaces start_byte..old_end_byte with new_text; changes are applied in order, each on the text produced by the previous one. If any change is invalid, the document is left unchanged"
+    )]
+    /// ドキュメントに変更を適用し、前のツリーを再利用してパースし直す
+    pub fn edit_document(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "id returned by open_document")]
+        document_id: String,
+        #[tool(param)]
+        #[schemars(description = "text changes to apply in order")]
+        changes: Vec<TextChange>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut documents = self.documents.lock().unwrap();
+        let document = documents
+            .edit(&document_id, &changes, self.config.limits.max_sql_bytes)
+            .map_err(|message| McpError::invalid_params(message, None))?;
+        let content = Content::json(json!({
+            "document_id": document_id,
+            "version": document.version,
+            "valid": !document.tree.root_node().has_error(),
+        }))?;
+        drop(documents);
+
+        self.notify_document_updated(&document_id);
+        Ok(CallToolResult::success(vec![content]))
+    }
+
+    #[tool(description = "Close an open document and free it")]
+    /// ドキュメントを閉じる
+    pub fn close_document(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "id returned by open_document")]
+        document_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.documents.lock().unwrap().close(&document_id) {
+            return Err(McpError::invalid_params(
+                format!("unknown document id: {}", document_id),
+                None,
+            ));
         }
-        cursor.goto_parent();
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .retain(|uri| parse_document_uri(uri).is_none_or(|(id, _)| id != document_id));
+
+        self.notify_resource_list_changed();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "closed document {}",
+            document_id
+        ))]))
+    }
+
+    #[tool(
+        description = "Search the tables, views and functions defined by CREATE statements in the workspace .sql files (set with --root or the client's roots) by a case-insensitive substring of their name"
+    )]
+    /// ワークスペースで定義されたオブジェクトを名前で検索する
+    pub fn workspace_symbols(
+        &self,
+        #[tool(param)]
+        #[schemars(description = "substring of the name to search for; all symbols if omitted")]
+        query: Option<String>,
+        #[tool(param)]
+        #[schemars(
+            description = "only return symbols of this kind: \"table\", \"view\" or \"function\""
+        )]
+        kind: Option<SymbolKind>,
+    ) -> Result<CallToolResult, McpError> {
+        let workspace = self.workspace.lock().unwrap();
+        if workspace.roots.is_empty() {
+            return Ok(no_workspace());
+        }
+
+        let symbols = workspace.symbols(query.as_deref().unwrap_or_default(), kind);
+
+        Ok(CallToolResult::success(vec![Content::json(symbols)?]))
     }
+
+    #[tool(
+        description = "Find the CREATE statements that define a table, view or function in the workspace .sql files. The name may be qualified with a schema"
+    )]
+    /// ワークスペースからオブジェクトの定義を探す
+    pub fn find_definition(
+        &self,
+        #[tool(param)]
+        #[schemars(
+            description = "name of the table, view or function, e.g. 'users' or 'public.users'"
+        )]
+        name: String,
+    ) -> Result<CallToolResult, McpError> {
+        let workspace = self.workspace.lock().unwrap();
+        if workspace.roots.is_empty() {
+            return Ok(no_workspace());
+        }
+
+        let definitions = workspace.find_definitions(&name);
+
+        Ok(CallToolResult::success(vec![Content::json(definitions)?]))
+    }
+
+    #[tool(
+        description = "Find the statements in the workspace .sql files that read, write, alter or drop a table or view, or call a function, with how each one uses it. The name may be qualified with a schema"
+    )]
+    /// ワークスペースからオブジェクトへの参照を探す
+    pub fn find_references(
+        &self,
+        #[tool(param)]
+        #[schemars(
+            description = "name of the table, view or function, e.g. 'users' or 'public.users'"
+        )]
+        name: String,
+    ) -> Result<CallToolResult, McpError> {
+        let workspace = self.workspace.lock().unwrap();
+        if workspace.roots.is_empty() {
+            return Ok(no_workspace());
+        }
+
+        let references = workspace.find_references(&name);
+
+        Ok(CallToolResult::success(vec![Content::json(references)?]))
+    }
+}
+
+/// リソースの更新をクライアントに通知する
+fn send_resource_updated(peer: Peer<RoleServer>, uris: Vec<String>) {
+    if uris.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        for uri in uris {
+            if let Err(e) = peer
+                .notify_resource_updated(ResourceUpdatedNotificationParam { uri })
+                .await
+            {
+                tracing::warn!("fail to notify resource update: {:?}", e);
+            }
+        }
+    });
+}
+
+/// リソースの一覧が変わったことをクライアントに通知する
+fn send_resource_list_changed(peer: Peer<RoleServer>) {
+    tokio::spawn(async move {
+        if let Err(e) = peer.notify_resource_list_changed().await {
+            tracing::warn!("fail to notify resource list change: {:?}", e);
+        }
+    });
+}
+
+/// ワークスペースのルートがない場合のツールの結果
+fn no_workspace() -> CallToolResult {
+    CallToolResult::error(vec![Content::text(
+        "no workspace roots: start the server with --root <dir> or use a client that provides roots",
+    )])
+}
+
+pub(crate) fn parse(sql: &str) -> Tree {
+    let language = tree_sitter_sql::language();
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(language).unwrap();
+
+    parser.parse(sql, None).unwrap()
+}
+
+/// 編集済みの古いツリーを再利用して SQL をパースし直す
+pub(crate) fn reparse(sql: &str, old_tree: &Tree) -> Tree {
+    let language = tree_sitter_sql::language();
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(language).unwrap();
+
+    parser.parse(sql, Some(old_tree)).unwrap()
+}
+
+/// SQL の一部 (start_byte..end_byte) だけをパースする
+/// ノードの位置は sql 全体での位置になる
+pub(crate) fn parse_range(sql: &str, start_byte: usize, end_byte: usize) -> Tree {
+    let language = tree_sitter_sql::language();
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(language).unwrap();
+    parser
+        .set_included_ranges(&[tree_sitter::Range {
+            start_byte,
+            end_byte,
+            start_point: point_at(sql, start_byte),
+            end_point: point_at(sql, end_byte),
+        }])
+        .unwrap();
+
+    parser.parse(sql, None).unwrap()
 }
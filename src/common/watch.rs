@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{self, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{DebounceEventResult, Debouncer, new_debouncer};
+use tree_sitter::Tree;
+
+use super::changes::Edit;
+use super::diagnostics::{Diagnostic, collect_diagnostics};
+use super::position::PositionEncoding;
+use super::tree_sitter_sql::{parse, reparse};
+use super::workspace::{find_sql_files, is_directory, is_sql_file};
+
+/// 監視している .sql ファイル
+pub struct WatchedFile {
+    pub text: String,
+    pub tree: Tree,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl WatchedFile {
+    fn new(text: String, encoding: PositionEncoding) -> Self {
+        let tree = parse(&text);
+        let diagnostics = collect_diagnostics(&tree, &text, encoding);
+        Self {
+            text,
+            tree,
+            diagnostics,
+        }
+    }
+
+    /// 前のツリーを再利用してパースし直す。構文エラーが変わった場合は true
+    fn update(&mut self, text: String, encoding: PositionEncoding) -> bool {
+        let edit = Edit::between(&self.text, &text);
+        self.tree.edit(&edit.input_edit(&self.text, &text));
+        self.tree = reparse(&text, &self.tree);
+        self.text = text;
+
+        let diagnostics = collect_diagnostics(&self.tree, &self.text, encoding);
+        let changed = diagnostics != self.diagnostics;
+        self.diagnostics = diagnostics;
+        changed
+    }
+}
+
+/// ファイルを読み直した結果、外から見えるもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Created,
+    /// 構文エラーが変わった
+    Modified,
+    Removed,
+}
+
+/// 監視しているディレクトリの下の .sql ファイル
+#[derive(Default)]
+pub struct WatchedFiles {
+    pub files: BTreeMap<PathBuf, WatchedFile>,
+    max_bytes: usize,
+    /// 構文エラーの位置の列を数える単位
+    encoding: PositionEncoding,
+}
+
+impl WatchedFiles {
+    /// dirs の下の .sql ファイルを読み込む
+    pub fn scan(dirs: &[PathBuf], max_bytes: usize, encoding: PositionEncoding) -> Self {
+        let mut files = Self {
+            files: BTreeMap::new(),
+            max_bytes,
+            encoding,
+        };
+        for dir in dirs {
+            files.update(dir);
+        }
+        files
+    }
+
+    /// path (ファイルまたはディレクトリ) の下のファイルを読み直し、外から見える変更を返す
+    /// 消えたファイル、読めないファイル、max_bytes を超えるファイルは一覧から除く
+    pub fn update(&mut self, path: &Path) -> Vec<(PathBuf, FileChange)> {
+        let mut paths: BTreeSet<_> = self
+            .files
+            .keys()
+            .filter(|known| known.starts_with(path))
+            .cloned()
+            .collect();
+        if is_directory(path) {
+            let mut found = Vec::new();
+            find_sql_files(path, &mut found);
+            paths.extend(found);
+        } else if is_sql_file(path) {
+            paths.insert(path.to_path_buf());
+        }
+
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let change = self.update_file(&path)?;
+                Some((path, change))
+            })
+            .collect()
+    }
+
+    fn update_file(&mut self, path: &Path) -> Option<FileChange> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) if text.len() <= self.max_bytes => Some(text),
+            Ok(_) => {
+                tracing::warn!("skip {}: too large", path.display());
+                None
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                tracing::warn!("skip {}: {}", path.display(), e);
+                None
+            }
+        };
+
+        match (self.files.get_mut(path), text) {
+            (Some(_), None) => {
+                self.files.remove(path);
+                Some(FileChange::Removed)
+            }
+            (None, None) => None,
+            (Some(file), Some(text)) => (file.text != text && file.update(text, self.encoding))
+                .then_some(FileChange::Modified),
+            (None, Some(text)) => {
+                self.files
+                    .insert(path.to_path_buf(), WatchedFile::new(text, self.encoding));
+                Some(FileChange::Created)
+            }
+        }
+    }
+}
+
+/// dirs を再帰的に監視し、debounce の間に起きた変更のパスをまとめて handler に渡す
+/// 返した Debouncer を破棄すると監視をやめる
+pub fn watch(
+    dirs: &[PathBuf],
+    debounce: Duration,
+    mut handler: impl FnMut(Vec<PathBuf>) + Send + 'static,
+) -> notify::Result<Debouncer<RecommendedWatcher>> {
+    let mut debouncer = new_debouncer(debounce, move |result: DebounceEventResult| match result {
+        Ok(events) => handler(events.into_iter().map(|event| event.path).collect()),
+        Err(e) => tracing::warn!("watch error: {:?}", e),
+    })?;
+    for dir in dirs {
+        debouncer.watcher().watch(dir, RecursiveMode::Recursive)?;
+    }
+    Ok(debouncer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const MAX_BYTES: usize = 1024;
+
+    fn scan(dir: &Path) -> WatchedFiles {
+        WatchedFiles::scan(&[dir.to_path_buf()], MAX_BYTES, PositionEncoding::Utf8)
+    }
+
+    #[test]
+    fn created_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = scan(dir.path());
+        assert!(files.files.is_empty());
+
+        let path = dir.path().join("a.sql");
+        fs::write(&path, "SELECT 1;").unwrap();
+        fs::write(dir.path().join("b.txt"), "SELECT 1;").unwrap();
+        assert_eq!(
+            files.update(dir.path()),
+            vec![(path.clone(), FileChange::Created)]
+        );
+        assert!(files.files[&path].diagnostics.is_empty());
+        // 変わっていないファイルを読み直しても何も起きない
+        assert!(files.update(&path).is_empty());
+    }
+
+    #[test]
+    fn modified_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.sql");
+        fs::write(&path, "SELECT 1;").unwrap();
+        let mut files = scan(dir.path());
+
+        // 構文エラーが変わらない変更は通知しないが、内容は取り込む
+        fs::write(&path, "SELECT 2;").unwrap();
+        assert!(files.update(&path).is_empty());
+        assert_eq!(files.files[&path].text, "SELECT 2;");
+
+        fs::write(&path, "SELECT (2;").unwrap();
+        assert_eq!(
+            files.update(&path),
+            vec![(path.clone(), FileChange::Modified)]
+        );
+        assert!(!files.files[&path].diagnostics.is_empty());
+
+        fs::write(&path, "SELECT (2);").unwrap();
+        assert_eq!(
+            files.update(&path),
+            vec![(path.clone(), FileChange::Modified)]
+        );
+        assert!(files.files[&path].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn removed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.sql");
+        fs::write(&path, "SELECT 1;").unwrap();
+        let mut files = scan(dir.path());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            files.update(&path),
+            vec![(path.clone(), FileChange::Removed)]
+        );
+        assert!(files.files.is_empty());
+        assert!(files.update(&path).is_empty());
+    }
+
+    #[test]
+    fn removed_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("a.sql"), "SELECT 1;").unwrap();
+        fs::write(sub.join("b.sql"), "SELECT 2;").unwrap();
+        fs::write(dir.path().join("c.sql"), "SELECT 3;").unwrap();
+        let mut files = scan(dir.path());
+        assert_eq!(files.files.len(), 3);
+
+        fs::remove_dir_all(&sub).unwrap();
+        assert_eq!(
+            files.update(&sub),
+            vec![
+                (sub.join("a.sql"), FileChange::Removed),
+                (sub.join("b.sql"), FileChange::Removed)
+            ]
+        );
+        assert_eq!(
+            files.files.keys().collect::<Vec<_>>(),
+            vec![&dir.path().join("c.sql")]
+        );
+    }
+
+    #[test]
+    fn renamed_into_and_out_of_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let mut files = scan(dir.path());
+
+        let from = outside.path().join("a.sql");
+        let to = dir.path().join("a.sql");
+        fs::write(&from, "SELECT 1;").unwrap();
+        fs::rename(&from, &to).unwrap();
+        assert_eq!(files.update(&to), vec![(to.clone(), FileChange::Created)]);
+
+        fs::rename(&to, &from).unwrap();
+        assert_eq!(files.update(&to), vec![(to.clone(), FileChange::Removed)]);
+        assert!(files.files.is_empty());
+    }
+
+    #[test]
+    fn symlink_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        let path = sub.join("a.sql");
+        fs::write(&path, "SELECT 1;").unwrap();
+        std::os::unix::fs::symlink(dir.path(), sub.join("loop")).unwrap();
+        let mut files = scan(dir.path());
+        assert_eq!(files.files.keys().collect::<Vec<_>>(), vec![&path]);
+
+        // リンクのディレクトリの変更は、リンクをたどらずに扱う
+        assert!(files.update(&sub.join("loop")).is_empty());
+        assert!(files.update(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn oversize_files_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.sql");
+        let large = dir.path().join("large.sql");
+        fs::write(&small, "SELECT 1;").unwrap();
+        fs::write(&large, "SELECT 1;".repeat(MAX_BYTES)).unwrap();
+        let mut files = scan(dir.path());
+        assert_eq!(files.files.keys().collect::<Vec<_>>(), vec![&small]);
+
+        fs::write(&small, "SELECT 1;".repeat(MAX_BYTES)).unwrap();
+        assert_eq!(
+            files.update(&small),
+            vec![(small.clone(), FileChange::Removed)]
+        );
+        assert!(files.files.is_empty());
+    }
+
+    #[test]
+    fn incremental_reparse_matches_fresh_parse() {
+        for (old, new) in [
+            ("SELECT 1;", "SELECT 12;"),
+            ("SELECT a FROM t;", "SELECT a, b FROM t WHERE a = 1;"),
+            ("SELECT a FROM t;", "SELECT (a FROM t;"),
+            ("SELECT (a FROM t;", "SELECT a FROM t;"),
+            ("SELECT 'é' FROM t;", "SELECT 'è' FROM t;"),
+            ("SELECT 1;\nSELECT 2;", "SELECT 1;"),
+            ("", "SELECT 1;"),
+        ] {
+            let mut file = WatchedFile::new(old.to_string(), PositionEncoding::Utf8);
+            file.update(new.to_string(), PositionEncoding::Utf8);
+            let fresh = parse(new);
+            assert_eq!(
+                file.tree.root_node().to_sexp(),
+                fresh.root_node().to_sexp(),
+                "{:?} -> {:?}",
+                old,
+                new
+            );
+            assert_eq!(
+                file.diagnostics,
+                collect_diagnostics(&fresh, new, PositionEncoding::Utf8)
+            );
+        }
+    }
+}
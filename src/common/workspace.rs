@@ -0,0 +1,443 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, Tree};
+
+use super::position::{PositionEncoding, SourceRange};
+use super::resources::percent_decode;
+use super::syntax::{is_name, node_text, normalize_identifier, split_qualified_name};
+use super::tables::{TableUsage, extract_tables};
+use super::tree_sitter_sql::parse;
+
+/// ワークスペースで定義されるオブジェクトの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    Table,
+    View,
+    /// 関数とプロシージャ
+    Function,
+}
+
+/// CREATE 文で定義されたオブジェクト
+#[derive(Debug, Clone, Serialize)]
+pub struct Symbol {
+    /// 修飾子を除いた名前 (書かれたとおり)
+    pub name: String,
+    pub schema: Option<String>,
+    pub kind: SymbolKind,
+    pub path: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// オブジェクトへの参照
+#[derive(Debug, Clone, Serialize)]
+pub struct Reference {
+    pub name: String,
+    pub schema: Option<String>,
+    /// テーブルとビューへの参照での使われ方。関数呼び出しの場合は None
+    pub usage: Option<TableUsage>,
+    pub path: String,
+    #[serde(flatten)]
+    pub range: SourceRange,
+}
+
+/// 1 ファイル分の索引
+pub struct IndexedFile {
+    pub definitions: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+impl IndexedFile {
+    /// 位置の列は encoding の単位で数える
+    pub fn new(path: &Path, text: &str, tree: &Tree, encoding: PositionEncoding) -> Self {
+        let path = path.display().to_string();
+        let mut definitions = Vec::new();
+        collect_definitions(tree.root_node(), text, encoding, &path, &mut definitions);
+        let mut references = Vec::new();
+        collect_function_calls(tree.root_node(), text, encoding, &path, &mut references);
+
+        references.extend(
+            extract_tables(tree, text, encoding)
+                .into_iter()
+                .filter(|table| !table.is_cte && table.usage != TableUsage::Create)
+                .map(|table| Reference {
+                    name: table.name,
+                    schema: table.schema,
+                    usage: Some(table.usage),
+                    path: path.clone(),
+                    range: table.range,
+                }),
+        );
+        references.sort_by_key(|reference| reference.range.start_byte);
+
+        Self {
+            definitions,
+            references,
+        }
+    }
+}
+
+/// ワークスペースのルート以下にある `*.sql` ファイルの索引
+#[derive(Default)]
+pub struct Workspace {
+    pub roots: Vec<PathBuf>,
+    pub files: BTreeMap<PathBuf, IndexedFile>,
+}
+
+impl Workspace {
+    /// ルート以下の `*.sql` ファイルを探してパースする
+    /// 読めないファイルと max_bytes を超えるファイルは飛ばす
+    pub fn index(roots: Vec<PathBuf>, max_bytes: usize, encoding: PositionEncoding) -> Self {
+        let mut paths = Vec::new();
+        for root in &roots {
+            find_sql_files(root, &mut paths);
+        }
+
+        let mut files = BTreeMap::new();
+        for path in paths {
+            match std::fs::read_to_string(&path) {
+                Ok(text) if text.len() <= max_bytes => {
+                    let tree = parse(&text);
+                    let file = IndexedFile::new(&path, &text, &tree, encoding);
+                    files.insert(path, file);
+                }
+                Ok(_) => tracing::warn!("skip {}: too large", path.display()),
+                Err(e) => tracing::warn!("skip {}: {}", path.display(), e),
+            }
+        }
+
+        Self { roots, files }
+    }
+
+    /// 名前に query を含む定義 (大文字小文字は区別しない)
+    pub fn symbols(&self, query: &str, kind: Option<SymbolKind>) -> Vec<&Symbol> {
+        let query = query.to_lowercase();
+        let mut symbols: Vec<_> = self
+            .definitions()
+            .filter(|symbol| kind.is_none_or(|kind| symbol.kind == kind))
+            .filter(|symbol| symbol.name.to_lowercase().contains(&query))
+            .collect();
+        symbols.sort_by_key(|symbol| normalize_identifier(&symbol.name));
+        symbols
+    }
+
+    /// `schema.name` または `name` の定義
+    pub fn find_definitions(&self, name: &str) -> Vec<&Symbol> {
+        let name = QualifiedName::parse(name);
+        self.definitions()
+            .filter(|symbol| name.matches(&symbol.name, symbol.schema.as_deref()))
+            .collect()
+    }
+
+    /// `schema.name` または `name` への参照
+    pub fn find_references(&self, name: &str) -> Vec<&Reference> {
+        let name = QualifiedName::parse(name);
+        self.files
+            .values()
+            .flat_map(|file| &file.references)
+            .filter(|reference| name.matches(&reference.name, reference.schema.as_deref()))
+            .collect()
+    }
+
+    fn definitions(&self) -> impl Iterator<Item = &Symbol> {
+        self.files.values().flat_map(|file| &file.definitions)
+    }
+}
+
+/// 検索する名前。スキーマが省略された場合はどのスキーマの名前にも一致する
+struct QualifiedName {
+    name: String,
+    schema: Option<String>,
+}
+
+impl QualifiedName {
+    fn parse(text: &str) -> Self {
+        let mut parts = split_qualified_name(text);
+        let name = normalize_identifier(&parts.pop().unwrap_or_default());
+        let schema = parts.pop().map(|schema| normalize_identifier(&schema));
+        Self { name, schema }
+    }
+
+    fn matches(&self, name: &str, schema: Option<&str>) -> bool {
+        normalize_identifier(name) == self.name
+            && self.schema.as_ref().is_none_or(|expected| {
+                schema.is_some_and(|schema| normalize_identifier(schema) == *expected)
+            })
+    }
+}
+
+/// 隠しディレクトリとシンボリックリンクのディレクトリを除き、再帰的に `*.sql` ファイルを探す
+pub fn find_sql_files(dir: &Path, paths: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("fail to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let mut entries: Vec<_> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if is_directory(&path) {
+            find_sql_files(&path, paths);
+        } else if is_sql_file(&path) && path.is_file() {
+            paths.push(path);
+        }
+    }
+}
+
+/// シンボリックリンクをたどらずにディレクトリかどうか調べる
+/// 親を指すリンクをたどると探索が終わらなくなるため、リンクのディレクトリは探さない
+pub fn is_directory(path: &Path) -> bool {
+    path.symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_dir())
+}
+
+pub fn is_sql_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("sql"))
+}
+
+fn collect_definitions(
+    node: Node,
+    src: &str,
+    encoding: PositionEncoding,
+    path: &str,
+    definitions: &mut Vec<Symbol>,
+) {
+    // CREATE TRIGGER ... EXECUTE FUNCTION などを定義とみなさないよう、文の種類で判定する
+    let kind = match node.kind() {
+        "create_table_statement" => Some(SymbolKind::Table),
+        "create_view_statement" | "create_materialized_view_statement" => Some(SymbolKind::View),
+        "create_function_statement" | "create_procedure_statement" => Some(SymbolKind::Function),
+        _ => None,
+    };
+    if let Some(kind) = kind {
+        let mut cursor = node.walk();
+        let name = node
+            .named_children(&mut cursor)
+            .find(|child| is_name(*child));
+        if let Some(name) = name {
+            let mut parts = split_qualified_name(node_text(name, src));
+            definitions.push(Symbol {
+                name: parts.pop().unwrap_or_default(),
+                schema: parts.pop(),
+                kind,
+                path: path.to_string(),
+                range: SourceRange::new(name, src, encoding),
+            });
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_definitions(child, src, encoding, path, definitions);
+    }
+}
+
+fn collect_function_calls(
+    node: Node,
+    src: &str,
+    encoding: PositionEncoding,
+    path: &str,
+    references: &mut Vec<Reference>,
+) {
+    if node.kind() == "function_call"
+        && let Some(function) = node
+            .child_by_field_name("function")
+            .or_else(|| node.named_child(0))
+            .filter(|function| is_name(*function))
+    {
+        let mut parts = split_qualified_name(node_text(function, src));
+        references.push(Reference {
+            name: parts.pop().unwrap_or_default(),
+            schema: parts.pop(),
+            usage: None,
+            path: path.to_string(),
+            range: SourceRange::new(function, src, encoding),
+        });
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_function_calls(child, src, encoding, path, references);
+    }
+}
+
+/// `file://` の URI をパスにする。ほかのスキームの場合は None
+pub fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // file://host/path のホスト部分を除く
+    let path = &rest[rest.find('/')?..];
+    percent_decode(path).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(files: &[(&str, &str)]) -> Workspace {
+        Workspace {
+            roots: Vec::new(),
+            files: files
+                .iter()
+                .map(|(path, text)| {
+                    let path = PathBuf::from(path);
+                    let file = IndexedFile::new(&path, text, &parse(text), PositionEncoding::Utf8);
+                    (path, file)
+                })
+                .collect(),
+        }
+    }
+
+    fn definitions(workspace: &Workspace, name: &str) -> Vec<(String, SymbolKind)> {
+        workspace
+            .find_definitions(name)
+            .into_iter()
+            .map(|symbol| (symbol.path.clone(), symbol.kind))
+            .collect()
+    }
+
+    fn references(workspace: &Workspace, name: &str) -> Vec<(String, usize)> {
+        workspace
+            .find_references(name)
+            .into_iter()
+            .map(|reference| (reference.path.clone(), reference.range.start.line))
+            .collect()
+    }
+
+    #[test]
+    fn schema_qualified_and_bare_names() {
+        let workspace = workspace(&[
+            ("/a.sql", "CREATE TABLE public.users (id int);"),
+            ("/b.sql", "CREATE TABLE audit.users (id int);"),
+            (
+                "/c.sql",
+                "SELECT * FROM public.users;\nSELECT * FROM users;\nSELECT * FROM audit.users;",
+            ),
+        ]);
+
+        assert_eq!(
+            definitions(&workspace, "users"),
+            vec![
+                ("/a.sql".to_string(), SymbolKind::Table),
+                ("/b.sql".to_string(), SymbolKind::Table)
+            ]
+        );
+        assert_eq!(
+            definitions(&workspace, "PUBLIC.Users"),
+            vec![("/a.sql".to_string(), SymbolKind::Table)]
+        );
+        assert!(definitions(&workspace, "other.users").is_empty());
+
+        assert_eq!(references(&workspace, "users").len(), 3);
+        assert_eq!(
+            references(&workspace, "audit.users"),
+            vec![("/c.sql".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn quoted_identifiers() {
+        let workspace = workspace(&[(
+            "/a.sql",
+            "CREATE TABLE \"Order Items\" (id int);\nSELECT * FROM \"Order Items\";\nSELECT * FROM order_items;",
+        )]);
+
+        assert_eq!(definitions(&workspace, "\"Order Items\"").len(), 1);
+        assert!(definitions(&workspace, "order items").is_empty());
+        assert_eq!(
+            references(&workspace, "\"Order Items\""),
+            vec![("/a.sql".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn cte_names_are_not_references() {
+        let workspace = workspace(&[(
+            "/a.sql",
+            "WITH users AS (SELECT 1) SELECT * FROM users;\nSELECT * FROM users;",
+        )]);
+
+        assert_eq!(
+            references(&workspace, "users"),
+            vec![("/a.sql".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn views_and_functions() {
+        let workspace = workspace(&[(
+            "/a.sql",
+            "CREATE VIEW active AS SELECT * FROM users;\nCREATE FUNCTION add_one(x int) RETURNS int AS $$ SELECT x + 1 $$ LANGUAGE sql;\nSELECT add_one(1) FROM active;",
+        )]);
+
+        assert_eq!(
+            definitions(&workspace, "active"),
+            vec![("/a.sql".to_string(), SymbolKind::View)]
+        );
+        assert_eq!(
+            definitions(&workspace, "add_one"),
+            vec![("/a.sql".to_string(), SymbolKind::Function)]
+        );
+        assert_eq!(
+            references(&workspace, "add_one"),
+            vec![("/a.sql".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn trigger_is_not_a_function_definition() {
+        let workspace = workspace(&[(
+            "/a.sql",
+            "CREATE TRIGGER audit_users AFTER INSERT ON users FOR EACH ROW EXECUTE FUNCTION audit();",
+        )]);
+
+        assert!(workspace.symbols("", Some(SymbolKind::Function)).is_empty());
+        assert!(definitions(&workspace, "audit_users").is_empty());
+    }
+
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("a.sql"), "SELECT 1;").unwrap();
+        fs::write(outside.path().join("b.sql"), "SELECT 2;").unwrap();
+        // 親を指すループと、ツリーの外を指すリンク
+        symlink(dir.path(), sub.join("loop")).unwrap();
+        symlink(outside.path(), dir.path().join("outside")).unwrap();
+        // ファイルへのリンクは読み、壊れたリンクは無視する
+        symlink(outside.path().join("b.sql"), dir.path().join("b.sql")).unwrap();
+        symlink(
+            dir.path().join("missing.sql"),
+            dir.path().join("broken.sql"),
+        )
+        .unwrap();
+
+        let mut paths = Vec::new();
+        find_sql_files(dir.path(), &mut paths);
+        assert_eq!(paths, vec![dir.path().join("b.sql"), sub.join("a.sql")]);
+    }
+}
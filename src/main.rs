@@ -1,8 +1,58 @@
-use anyhow::Result;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use common::config::Config;
 use common::tree_sitter_sql::ParseSqlTool;
 use rmcp::{ServiceExt, transport::stdio};
 use tracing_subscriber::{self, EnvFilter};
 mod common;
+
+/// コマンドライン引数
+#[derive(Debug, Default)]
+struct Args {
+    /// --config で指定された設定ファイル
+    config: Option<PathBuf>,
+    /// --root で指定されたワークスペースのルート (複数指定可)
+    roots: Vec<PathBuf>,
+    /// --watch で指定された、変更を監視するディレクトリ (複数指定可)
+    watch: Vec<PathBuf>,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = iter.next().context("--config requires a path")?;
+                    args.config = Some(PathBuf::from(path));
+                }
+                "--root" => {
+                    let path = iter.next().context("--root requires a path")?;
+                    args.roots.push(PathBuf::from(path));
+                }
+                "--watch" => {
+                    let path = iter.next().context("--watch requires a path")?;
+                    args.watch.push(PathBuf::from(path));
+                }
+                _ => {
+                    if let Some(path) = arg.strip_prefix("--config=") {
+                        args.config = Some(PathBuf::from(path));
+                    } else if let Some(path) = arg.strip_prefix("--root=") {
+                        args.roots.push(PathBuf::from(path));
+                    } else if let Some(path) = arg.strip_prefix("--watch=") {
+                        args.watch.push(PathBuf::from(path));
+                    } else {
+                        bail!("unknown argument: {}", arg);
+                    }
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
 /// npx @modelcontextprotocol/inspector cargo run -q
 
 #[tokio::main]
@@ -14,12 +64,20 @@ async fn main() -> Result<()> {
         .with_ansi(false)
         .init();
 
+    let args = Args::parse()?;
+    let mut config = Config::load(args.config.as_deref())?;
+    config.watch.dirs.extend(args.watch);
+
     tracing::info!("Starting MCP server");
 
     // Create an instance of our counter router
-    let service = ParseSqlTool::new().serve(stdio()).await.inspect_err(|e| {
-        tracing::error!("serving error: {:?}", e);
-    })?;
+    let service = ParseSqlTool::new(config)
+        .with_roots(args.roots)
+        .serve(stdio())
+        .await
+        .inspect_err(|e| {
+            tracing::error!("serving error: {:?}", e);
+        })?;
 
     service.waiting().await?;
     Ok(())